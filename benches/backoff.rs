@@ -0,0 +1,48 @@
+// Compares the default exponential backoff against retrying the CAS as soon
+// as the lock looks free. Run with `cargo bench --bench backoff`.
+
+#[cfg(not(feature = "single-thread"))]
+use mutex::{Mutex, DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS};
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const THREADS: usize = 64;
+#[cfg(not(feature = "single-thread"))]
+const ITERATIONS: usize = 10_000;
+
+#[cfg(not(feature = "single-thread"))]
+fn contend(l: &Mutex<usize>) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    l.with_lock(|v| *v += 1);
+                }
+            });
+        }
+    });
+    assert_eq!(l.with_lock(|v| *v), THREADS * ITERATIONS);
+    start.elapsed()
+}
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    let backoff = contend(&Mutex::with_backoff(
+        0,
+        DEFAULT_INITIAL_SPINS,
+        DEFAULT_MAX_SPINS,
+    ));
+    let no_backoff = contend(&Mutex::with_backoff(0, 0, 0));
+
+    println!("{THREADS} threads x {ITERATIONS} increments");
+    println!("  exponential backoff: {backoff:?}");
+    println!("  no backoff:          {no_backoff:?}");
+}
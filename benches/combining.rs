@@ -0,0 +1,50 @@
+// Flat combining against the plain spin lock on a shared counter, where the
+// critical section is nothing but the increment. Run with
+// `cargo bench --bench combining`.
+
+#[cfg(not(feature = "single-thread"))]
+use mutex::{FlatCombiningMutex, Mutex};
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const THREADS: [usize; 3] = [8, 32, 64];
+#[cfg(not(feature = "single-thread"))]
+const ITERATIONS: usize = 10_000;
+
+#[cfg(not(feature = "single-thread"))]
+fn contend(threads: usize, increment: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    increment();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    println!("increments per thread: {ITERATIONS}");
+    for threads in THREADS {
+        let plain = Mutex::new(0);
+        let spin = contend(threads, || plain.with_lock(|v| *v += 1));
+        assert_eq!(plain.into_inner(), threads * ITERATIONS);
+
+        let combining = FlatCombiningMutex::new(0);
+        let combined = contend(threads, || combining.apply(|v| *v += 1));
+        assert_eq!(combining.into_inner(), threads * ITERATIONS);
+
+        println!("  {threads:2} threads  Mutex: {spin:?}  FlatCombiningMutex: {combined:?}");
+    }
+}
@@ -0,0 +1,152 @@
+// Throughput and fairness of Mutex against std::sync::Mutex and
+// parking_lot::Mutex for 1 to 16 threads, with short and long critical
+// sections. Run with `cargo bench --bench contention`, criterion keeps the
+// last run as the baseline to compare against.
+//
+// Throughput is measured by criterion: every iteration is one acquisition,
+// split evenly over the threads. Fairness is printed at the end: every thread
+// hammers the lock for a fixed time, and the least successful thread's count
+// over the most successful one's is 1.0 when everyone got the same share.
+
+#[cfg(not(feature = "single-thread"))]
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+#[cfg(not(feature = "single-thread"))]
+use std::hint::spin_loop;
+#[cfg(not(feature = "single-thread"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(feature = "single-thread"))]
+use std::sync::Barrier;
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const THREADS: [usize; 5] = [1, 2, 4, 8, 16];
+// Spins inside the critical section
+#[cfg(not(feature = "single-thread"))]
+const SECTIONS: [(&str, usize); 2] = [("short", 1), ("long", 200)];
+// How long each thread count runs for the fairness summary
+#[cfg(not(feature = "single-thread"))]
+const FAIRNESS_RUN: Duration = Duration::from_millis(200);
+
+#[cfg(not(feature = "single-thread"))]
+fn work(v: &mut usize, spins: usize) {
+    for _ in 0..spins {
+        spin_loop();
+    }
+    *v += 1;
+}
+
+// Time for `threads` threads to make `iters` acquisitions between them,
+// counted from the moment they are all running
+#[cfg(not(feature = "single-thread"))]
+fn contend<L: Sync>(lock: &L, threads: usize, iters: u64, acquire: impl Fn(&L) + Sync) -> Duration {
+    let start = Barrier::new(threads + 1);
+    thread::scope(|s| {
+        for t in 0..threads as u64 {
+            let (start, acquire) = (&start, &acquire);
+            // The first threads take the remainder
+            let mine = iters / threads as u64 + u64::from(t < iters % threads as u64);
+            s.spawn(move || {
+                start.wait();
+                for _ in 0..mine {
+                    acquire(lock);
+                }
+            });
+        }
+        start.wait();
+        let begin = Instant::now();
+        // Leaving the scope joins them
+        begin
+    })
+    .elapsed()
+}
+
+#[cfg(not(feature = "single-thread"))]
+fn throughput(c: &mut Criterion) {
+    for (name, spins) in SECTIONS {
+        let mut group = c.benchmark_group(format!("contention/{name}"));
+        group
+            .throughput(Throughput::Elements(1))
+            .warm_up_time(Duration::from_millis(500))
+            .measurement_time(Duration::from_secs(2));
+        for threads in THREADS {
+            group.bench_with_input(BenchmarkId::new("Mutex", threads), &threads, |b, &n| {
+                let l = mutex::Mutex::new(0);
+                b.iter_custom(|iters| contend(&l, n, iters, |l| l.with_lock(|v| work(v, spins))));
+            });
+            group.bench_with_input(BenchmarkId::new("std", threads), &threads, |b, &n| {
+                let l = std::sync::Mutex::new(0);
+                b.iter_custom(|iters| {
+                    contend(&l, n, iters, |l| work(&mut l.lock().unwrap(), spins))
+                });
+            });
+            group.bench_with_input(
+                BenchmarkId::new("parking_lot", threads),
+                &threads,
+                |b, &n| {
+                    let l = parking_lot::Mutex::new(0);
+                    b.iter_custom(|iters| contend(&l, n, iters, |l| work(&mut l.lock(), spins)));
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+// Least over most acquisitions per thread while `threads` hammer the lock
+#[cfg(not(feature = "single-thread"))]
+fn fairness_of<L: Sync>(lock: &L, threads: usize, acquire: impl Fn(&L) + Sync) -> f64 {
+    let stop = AtomicBool::new(false);
+    let counts: Vec<usize> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut n = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        acquire(lock);
+                        n += 1;
+                    }
+                    n
+                })
+            })
+            .collect();
+        thread::sleep(FAIRNESS_RUN);
+        stop.store(true, Ordering::Relaxed);
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+    *min as f64 / (*max).max(1) as f64
+}
+
+// Not something criterion measures, printed as a summary after the groups
+#[cfg(not(feature = "single-thread"))]
+fn fairness(_: &mut Criterion) {
+    for (name, spins) in SECTIONS {
+        println!("fairness, {name} critical section ({spins} spins)");
+        println!("  threads     Mutex       std  parking_lot");
+        for threads in THREADS {
+            let spin = fairness_of(&mutex::Mutex::new(0), threads, |l| {
+                l.with_lock(|v| work(v, spins))
+            });
+            let std = fairness_of(&std::sync::Mutex::new(0), threads, |l| {
+                work(&mut l.lock().unwrap(), spins)
+            });
+            let pl = fairness_of(&parking_lot::Mutex::new(0), threads, |l| {
+                work(&mut l.lock(), spins)
+            });
+            println!("  {threads:7}  {spin:8.2}  {std:8.2}  {pl:11.2}");
+        }
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+criterion_group!(benches, throughput, fairness);
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+// Eight threads each hammer their own mutex out of an array of eight. Without
+// padding the mutexes share cache lines and the threads slow each other down
+// even though no lock is ever contended. Run with `cargo bench --bench false_sharing`.
+
+#[cfg(not(feature = "single-thread"))]
+use mutex::{CachePadded, Mutex};
+#[cfg(not(feature = "single-thread"))]
+use std::ops::Deref;
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const LOCKS: usize = 8;
+#[cfg(not(feature = "single-thread"))]
+const ITERATIONS: usize = 1_000_000;
+
+#[cfg(not(feature = "single-thread"))]
+fn run<L: Deref<Target = Mutex<usize>> + Sync>(locks: &[L; LOCKS]) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for l in locks {
+            s.spawn(move || {
+                for _ in 0..ITERATIONS {
+                    l.with_lock(|v| *v += 1);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// Deref so both arrays go through the same code
+#[cfg(not(feature = "single-thread"))]
+struct Plain(Mutex<usize>);
+
+#[cfg(not(feature = "single-thread"))]
+impl Deref for Plain {
+    type Target = Mutex<usize>;
+
+    fn deref(&self) -> &Mutex<usize> {
+        &self.0
+    }
+}
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    let plain: [Plain; LOCKS] = std::array::from_fn(|_| Plain(Mutex::new(0)));
+    let padded: [CachePadded<Mutex<usize>>; LOCKS] = Default::default();
+
+    let plain_time = run(&plain);
+    let padded_time = run(&padded);
+
+    println!("{LOCKS} threads x {ITERATIONS} increments on their own mutex");
+    println!("  adjacent: {plain_time:?}");
+    println!("  padded:   {padded_time:?}");
+}
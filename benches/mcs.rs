@@ -0,0 +1,49 @@
+// Compares the MCS queue lock with the test-and-set Mutex as the number of
+// contending threads grows. Run with `cargo bench --bench mcs`.
+//
+// Queue locks hand the lock to one specific waiter, if there are more threads than
+// cores that waiter is often not running and everyone waits for the scheduler. Expect
+// McsMutex to fall far behind once the thread count passes the core count.
+
+#[cfg(not(feature = "single-thread"))]
+use mutex::{McsMutex, Mutex};
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const TOTAL_INCREMENTS: usize = 640_000;
+
+#[cfg(not(feature = "single-thread"))]
+fn contend(threads: usize, incr: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..TOTAL_INCREMENTS / threads {
+                    incr();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    println!("{TOTAL_INCREMENTS} increments split across threads");
+    for threads in [1, 8, 64] {
+        let spin = Mutex::new(0usize);
+        let mcs = McsMutex::new(0usize);
+        let spin_time = contend(threads, || spin.with_lock(|v| *v += 1));
+        let mcs_time = contend(threads, || mcs.with_lock(|v| *v += 1));
+        assert_eq!(spin.with_lock(|v| *v), TOTAL_INCREMENTS);
+        assert_eq!(mcs.with_lock(|v| *v), TOTAL_INCREMENTS);
+        println!("  {threads:>2} threads: Mutex {spin_time:?}, McsMutex {mcs_time:?}");
+    }
+}
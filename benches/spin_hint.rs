@@ -0,0 +1,87 @@
+// Compares the lock against the same spin lock without the `spin_loop` hint in
+// its inner wait loop. Run with `cargo bench --bench spin_hint`.
+
+#[cfg(not(feature = "single-thread"))]
+use mutex::Mutex;
+#[cfg(not(feature = "single-thread"))]
+use std::cell::UnsafeCell;
+#[cfg(not(feature = "single-thread"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(feature = "single-thread"))]
+use std::thread;
+#[cfg(not(feature = "single-thread"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "single-thread"))]
+const THREADS: usize = 16;
+#[cfg(not(feature = "single-thread"))]
+const ITERATIONS: usize = 100_000;
+
+// The lock as it was before the hint was added
+#[cfg(not(feature = "single-thread"))]
+struct NoHintMutex<T> {
+    locked: AtomicBool,
+    v: UnsafeCell<T>,
+}
+
+#[cfg(not(feature = "single-thread"))]
+unsafe impl<T> Sync for NoHintMutex<T> where T: Send {}
+
+#[cfg(not(feature = "single-thread"))]
+impl<T> NoHintMutex<T> {
+    fn new(t: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    #[allow(clippy::missing_spin_loop)]
+    fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {}
+        }
+        let ret = f(unsafe { &mut *self.v.get() });
+        self.locked.store(false, Ordering::Release);
+        ret
+    }
+}
+
+#[cfg(not(feature = "single-thread"))]
+fn contend(incr: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    incr();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// Contends from several threads, which single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    let hinted = Mutex::new(0usize);
+    let unhinted = NoHintMutex::new(0usize);
+
+    let with_hint = contend(|| hinted.with_lock(|v| *v += 1));
+    let without_hint = contend(|| unhinted.with_lock(|v| *v += 1));
+
+    assert_eq!(hinted.with_lock(|v| *v), THREADS * ITERATIONS);
+    assert_eq!(unhinted.with_lock(|v| *v), THREADS * ITERATIONS);
+
+    println!("{THREADS} threads x {ITERATIONS} increments");
+    println!("  spin_loop hint:    {with_hint:?}");
+    println!("  no hint:           {without_hint:?}");
+}
@@ -0,0 +1,5 @@
+fn main() {
+    println!("cargo:rerun-if-changed=capi.c");
+    println!("cargo:rerun-if-changed=../include/mutex_raw.h");
+    cc::Build::new().file("capi.c").compile("mutex_capi_test");
+}
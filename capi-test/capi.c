@@ -0,0 +1,20 @@
+/* Called from src/lib.rs, returns 0 on success or the failed step */
+#include <stddef.h>
+
+#include "../include/mutex_raw.h"
+
+int mutex_capi_smoke(void) {
+    RawSpinMutex *m = mutex_raw_new();
+    if (!m)
+        return 1;
+    mutex_raw_lock(m);
+    if (mutex_raw_try_lock(m))
+        return 2;
+    mutex_raw_unlock(m);
+    if (!mutex_raw_try_lock(m))
+        return 3;
+    mutex_raw_unlock(m);
+    mutex_raw_free(m);
+    mutex_raw_free(NULL);
+    return 0;
+}
@@ -0,0 +1,16 @@
+// Runs capi.c against the C API, build.rs compiles it
+#![cfg(test)]
+
+// Nothing is used from Rust, the C code needs the symbols linked in
+extern crate mutex;
+
+use std::ffi::c_int;
+
+extern "C" {
+    fn mutex_capi_smoke() -> c_int;
+}
+
+#[test]
+fn c_caller_locks_and_unlocks() {
+    assert_eq!(unsafe { mutex_capi_smoke() }, 0);
+}
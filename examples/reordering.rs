@@ -0,0 +1,27 @@
+// The experiment itself lives in the library, see mutex::run_reorder_experiment.
+// In a nutshell:
+//   tx: x = true
+//   ty: y = true
+//   t1: wait for x, then z += 1 if y
+//   t2: wait for y, then z += 1 if x
+fn main() {
+    let hist = mutex::run_reorder_experiment(1000);
+
+    // What are the possible value for z?
+    //  - Is 0 possible?
+    //    Restrictions
+    //      We know that t1 must run "after" tx otherwise infinite loop
+    //      We know that t2 must run "after" ty otherwise infinite loop
+    // Given that
+    //   .. tx .. t1 ..
+    //      ty t2 tx t1 -> t1 will incr z (because tx ran before it)
+    //      ty tx t2 t1 .. t1 and t2 will incr z (because ty ran before t2 and tx ran before t1)
+    //      .. tx .. t1 ty t2 -> t2 will incr z  (becasue y is true(loop will break) and x is also true so incr can happen)
+    //  - Is 1 possible?
+    //    Yes: tx, t1, ty, t2
+    //  - Is 2 possible?
+    //    Yes: tx, ty, t1, t2
+    for z in 0..=2 {
+        println!("z = {z}: {} runs", hist.get(&z).copied().unwrap_or(0));
+    }
+}
@@ -0,0 +1,31 @@
+// A Mutex on the stack shared with scoped threads, no Box::leak or Arc needed.
+// The scope joins every thread before returning, so borrowing is enough
+#[cfg(not(feature = "single-thread"))]
+use mutex::Mutex;
+#[cfg(not(feature = "single-thread"))]
+use std::thread::scope;
+
+// Sharing between threads is what single-thread rules out
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    let counter = Mutex::new(0u64);
+    let log = Mutex::new(Vec::new());
+    scope(|s| {
+        for id in 0..8 {
+            let (counter, log) = (&counter, &log);
+            s.spawn(move || {
+                for _ in 0..1000 {
+                    counter.with_lock(|v| *v += 1);
+                }
+                log.with_lock(|v| v.push(id));
+            });
+        }
+    });
+    // Every thread is done, the mutexes can be taken apart again
+    let mut log = log.into_inner();
+    log.sort();
+    println!("counter = {}, threads = {log:?}", counter.into_inner());
+}
@@ -0,0 +1,29 @@
+// A global Mutex, no Box::leak needed since Mutex::new is const
+// A static needs Sync, which single-thread takes away
+#[cfg(not(feature = "single-thread"))]
+use mutex::Mutex;
+#[cfg(not(feature = "single-thread"))]
+use std::thread::spawn;
+
+#[cfg(not(feature = "single-thread"))]
+static COUNTER: Mutex<u64> = Mutex::new(0);
+
+#[cfg(feature = "single-thread")]
+fn main() {}
+
+#[cfg(not(feature = "single-thread"))]
+fn main() {
+    let handles: Vec<_> = (0..10)
+        .map(|_| {
+            spawn(|| {
+                for _ in 0..1000 {
+                    COUNTER.with_lock(|v| *v += 1);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    println!("counter = {}", COUNTER.with_lock(|v| *v));
+}
@@ -0,0 +1,34 @@
+/*
+ * C API of the raw spin lock, built with the `capi` feature.
+ *
+ * Written by hand to match src/capi.rs. It can be regenerated with
+ *
+ *     cbindgen --lang c --crate mutex --output include/mutex_raw.h
+ *
+ * The lock is never locked recursively and must be unlocked on the thread
+ * that locked it.
+ */
+#ifndef MUTEX_RAW_H
+#define MUTEX_RAW_H
+
+#include <stdbool.h>
+
+typedef struct RawSpinMutex RawSpinMutex;
+
+/* Allocates a new unlocked lock, free it with mutex_raw_free. */
+RawSpinMutex *mutex_raw_new(void);
+
+/* Spins until the lock is acquired. */
+void mutex_raw_lock(const RawSpinMutex *m);
+
+/* Takes the lock if it is free, returns whether it did. Only fails when the
+ * lock is held, never spuriously. */
+bool mutex_raw_try_lock(const RawSpinMutex *m);
+
+/* Releases a lock held by the calling thread. */
+void mutex_raw_unlock(const RawSpinMutex *m);
+
+/* Frees an unlocked lock, NULL is ignored. */
+void mutex_raw_free(RawSpinMutex *m);
+
+#endif
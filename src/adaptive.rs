@@ -0,0 +1,176 @@
+use crate::waiters::WaitQueue;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+
+// Three states instead of a bool so the releasing thread knows whether it has to
+// look at the wait queue at all. Uncontended lock/unlock never touches it
+const UNLOCKED: u8 = 0;
+const LOCKED: u8 = 1;
+// Locked and somebody may be parked
+const CONTENDED: u8 = 2;
+
+// Spins before parking by default
+const DEFAULT_SPIN_LIMIT: usize = 100;
+
+/// A lock that spins briefly and then parks the thread until the holder
+/// releases it. Better suited than [`Mutex`](crate::Mutex) for long critical
+/// sections.
+pub struct AdaptiveMutex<T> {
+    state: AtomicU8,
+    spin_limit: usize,
+    waiters: WaitQueue,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for AdaptiveMutex<T> where T: Send {}
+
+impl<T> AdaptiveMutex<T> {
+    /// Creates a new unlocked mutex holding `t` that spins 100 times before
+    /// parking.
+    pub fn new(t: T) -> Self {
+        Self::with_spin_limit(t, DEFAULT_SPIN_LIMIT)
+    }
+
+    /// Creates a new unlocked mutex that spins `spin_limit` times before
+    /// parking a waiter.
+    pub fn with_spin_limit(t: T, spin_limit: usize) -> Self {
+        Self {
+            state: AtomicU8::new(UNLOCKED),
+            spin_limit,
+            waiters: WaitQueue::new(),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Acquires the lock, parking the thread if spinning didn't get it.
+    pub fn lock(&self) -> AdaptiveMutexGuard<'_, T> {
+        for _ in 0..self.spin_limit {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            std::hint::spin_loop();
+        }
+
+        loop {
+            // Queue up *before* announcing ourselves, the releasing thread only looks
+            // at the queue when it sees CONTENDED so it must already find us there
+            self.waiters.register();
+            // We can't tell if other waiters are still parked so we keep CONTENDED even
+            // when this swap hands us the lock, worst case the unlock wakes nobody
+            if self.state.swap(CONTENDED, Ordering::Acquire) == UNLOCKED {
+                self.waiters.deregister();
+                return AdaptiveMutexGuard::new(self);
+            }
+            // park can return spuriously, that's fine we just go around again
+            thread::park();
+        }
+    }
+
+    /// Attempts to take the lock without spinning or parking.
+    #[must_use]
+    pub fn try_lock(&self) -> Option<AdaptiveMutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| AdaptiveMutexGuard::new(self))
+    }
+
+    /// Acquires the lock, runs `f` on the protected value and releases it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            self.waiters.wake_one();
+        }
+    }
+}
+
+/// RAII guard returned by [`AdaptiveMutex::lock`].
+#[must_use = "if unused the lock is released immediately"]
+pub struct AdaptiveMutexGuard<'a, T> {
+    mutex: &'a AdaptiveMutex<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for AdaptiveMutexGuard<'_, T> where T: Sync {}
+
+impl<'a, T> AdaptiveMutexGuard<'a, T> {
+    fn new(mutex: &'a AdaptiveMutex<T>) -> Self {
+        Self {
+            mutex,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for AdaptiveMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T> DerefMut for AdaptiveMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+impl<T> Drop for AdaptiveMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::{scope, sleep};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn counter() {
+        let l = AdaptiveMutex::with_spin_limit(0, 10);
+        scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.with_lock(|v| *v), 16 * 1000);
+    }
+
+    #[test]
+    fn waiter_parks_while_lock_is_held() {
+        let l = AdaptiveMutex::new(0);
+        let guard = l.lock();
+        scope(|s| {
+            let waiter = s.spawn(|| {
+                let start = Instant::now();
+                l.with_lock(|v| *v += 1);
+                start.elapsed()
+            });
+            // Give the waiter time to burn through its spins and park
+            while l.waiters.len() == 0 {
+                thread::yield_now();
+            }
+            assert_eq!(l.state.load(Ordering::Relaxed), CONTENDED);
+            sleep(Duration::from_millis(50));
+            // Still parked, it never got the lock while we slept
+            assert_eq!(l.waiters.len(), 1);
+            drop(guard);
+            assert!(waiter.join().unwrap() >= Duration::from_millis(50));
+        });
+        assert_eq!(l.with_lock(|v| *v), 1);
+        assert_eq!(l.waiters.len(), 0);
+    }
+}
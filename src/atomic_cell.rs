@@ -0,0 +1,281 @@
+use crate::sync::const_fn;
+use crate::Mutex;
+use core::mem::{self, size_of};
+use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+/// A cell for `Copy` values that doesn't lock when it doesn't have to. Values
+/// the size of a native atomic are loaded and stored with it, anything bigger
+/// goes through a [`Mutex`].
+///
+/// The atomic path reads the value as an integer, so only [`NoPadding`] types
+/// can take it, through [`AtomicCell::new`]. Any other `Copy` type goes through
+/// the lock with [`AtomicCell::new_locked`].
+///
+/// ```
+/// use mutex::AtomicCell;
+///
+/// let c = AtomicCell::new(7u32);
+/// assert!(AtomicCell::<u32>::is_lock_free());
+/// assert_eq!(c.swap(8), 7);
+/// assert_eq!(c.load(), 8);
+/// ```
+pub struct AtomicCell<T> {
+    // Set once by the constructor, only new can make it true
+    atomic: bool,
+    inner: Mutex<Aligned<T>>,
+}
+
+/// Types whose values have every byte initialized, so [`AtomicCell`] can load
+/// and store them as an integer of the same size.
+///
+/// Implemented for the primitive integers, floats, `bool`, `char`, raw
+/// pointers and arrays of these. Tuples and structs can have padding, which
+/// would be read as uninitialized bytes, so they aren't:
+///
+/// ```compile_fail
+/// // u8 then a byte of padding then u16
+/// let c = mutex::AtomicCell::new((1u8, 2u16));
+/// ```
+///
+/// # Safety
+///
+/// The type must have no padding bytes, between fields or at the end, and no
+/// other uninitialized bytes in any value.
+pub unsafe trait NoPadding: Copy {}
+
+macro_rules! no_padding {
+    ($($t:ty),*) => {$(
+        unsafe impl NoPadding for $t {}
+    )*};
+}
+
+no_padding!(u8, i8, u16, i16, u32, i32, u64, i64, usize, isize, f32, f64, bool, char);
+
+unsafe impl<T: ?Sized> NoPadding for *const T {}
+unsafe impl<T: ?Sized> NoPadding for *mut T {}
+unsafe impl<T: NoPadding, const N: usize> NoPadding for [T; N] {}
+
+// The widest atomic we use is usize, aligning for it lets any value of that
+// size be accessed through one
+#[cfg_attr(target_pointer_width = "64", repr(C, align(8)))]
+#[cfg_attr(target_pointer_width = "32", repr(C, align(4)))]
+#[cfg_attr(target_pointer_width = "16", repr(C, align(2)))]
+struct Aligned<T>(T);
+
+/// Whether a `T` can be accessed with a single native atomic.
+const fn is_lock_free<T>() -> bool {
+    matches!(size_of::<T>(), 1 | 2 | 4 | 8) && size_of::<T>() <= size_of::<usize>()
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Integers with a matching atomic. Sealed so the dispatch in AtomicCell can
+/// rely on there being exactly these.
+trait AtomicUnit: sealed::Sealed + Copy {
+    unsafe fn load(p: *mut Self) -> Self;
+    unsafe fn store(p: *mut Self, v: Self);
+    unsafe fn swap(p: *mut Self, v: Self) -> Self;
+}
+
+macro_rules! atomic_unit {
+    ($($int:ty => $atomic:ty),*) => {$(
+        impl sealed::Sealed for $int {}
+
+        impl AtomicUnit for $int {
+            unsafe fn load(p: *mut Self) -> Self {
+                <$atomic>::from_ptr(p).load(Ordering::Acquire)
+            }
+
+            unsafe fn store(p: *mut Self, v: Self) {
+                <$atomic>::from_ptr(p).store(v, Ordering::Release)
+            }
+
+            unsafe fn swap(p: *mut Self, v: Self) -> Self {
+                <$atomic>::from_ptr(p).swap(v, Ordering::AcqRel)
+            }
+        }
+    )*};
+}
+
+atomic_unit!(u8 => AtomicU8, u16 => AtomicU16, u32 => AtomicU32, usize => AtomicUsize);
+
+// Picks the integer as wide as `T`. Only called on cells made by new, where
+// is_lock_free::<T>() held and T: NoPadding. The match is on a constant so the
+// other arms compile away
+macro_rules! with_unit {
+    ($t:ty, | $u:ident | $body:expr) => {
+        match size_of::<$t>() {
+            1 => {
+                type $u = u8;
+                $body
+            }
+            2 => {
+                type $u = u16;
+                $body
+            }
+            4 if size_of::<usize>() > 4 => {
+                type $u = u32;
+                $body
+            }
+            _ => {
+                type $u = usize;
+                $body
+            }
+        }
+    };
+}
+
+impl<T: NoPadding> AtomicCell<T> {
+    const_fn! {
+        /// Creates a new cell holding `t`, lock free if
+        /// [`is_lock_free`](Self::is_lock_free) says so.
+        pub const fn new(t: T) -> Self {
+            Self {
+                atomic: is_lock_free::<T>(),
+                inner: Mutex::new(Aligned(t)),
+            }
+        }
+    }
+
+    /// Whether the `load`, `store` and `swap` of a cell made by
+    /// [`AtomicCell::new`] use a native atomic for this `T` instead of taking
+    /// the lock.
+    pub const fn is_lock_free() -> bool {
+        is_lock_free::<T>()
+    }
+}
+
+impl<T: Copy> AtomicCell<T> {
+    const_fn! {
+        /// Creates a new cell holding `t` that always takes the lock. For types
+        /// that aren't [`NoPadding`], or ones that may be and just don't say.
+        pub const fn new_locked(t: T) -> Self {
+            Self {
+                atomic: false,
+                inner: Mutex::new(Aligned(t)),
+            }
+        }
+    }
+
+    /// Returns a copy of the value.
+    pub fn load(&self) -> T {
+        if !self.atomic {
+            return self.inner.with_lock(|v| v.0);
+        }
+        let p = self.inner.data_ptr();
+        with_unit!(T, |U| unsafe { mem::transmute_copy(&U::load(p.cast())) })
+    }
+
+    /// Replaces the value.
+    pub fn store(&self, t: T) {
+        if !self.atomic {
+            return self.inner.with_lock(|v| v.0 = t);
+        }
+        let p = self.inner.data_ptr();
+        with_unit!(T, |U| unsafe {
+            U::store(p.cast(), mem::transmute_copy(&t))
+        })
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn swap(&self, t: T) -> T {
+        if !self.atomic {
+            return self.inner.with_lock(|v| mem::replace(&mut v.0, t));
+        }
+        let p = self.inner.data_ptr();
+        with_unit!(T, |U| unsafe {
+            mem::transmute_copy(&U::swap(p.cast(), mem::transmute_copy(&t)))
+        })
+    }
+
+    /// Consumes the cell and returns the value.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().0
+    }
+}
+
+impl<T: NoPadding + Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "single-thread"))]
+    use std::thread::scope;
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn u64_is_atomic() {
+        assert_eq!(AtomicCell::<u64>::is_lock_free(), size_of::<usize>() >= 8);
+        assert!(AtomicCell::<u8>::is_lock_free());
+        assert!(AtomicCell::<[u16; 2]>::is_lock_free());
+
+        let c = AtomicCell::new(0u64);
+        scope(|s| {
+            for t in 1..=4u64 {
+                let c = &c;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let old = c.swap(t << 32 | t);
+                        assert_eq!(old >> 32, old & 0xffff_ffff);
+                    }
+                });
+            }
+        });
+        let v = c.load();
+        assert!((1..=4).contains(&v) || (1..=4).contains(&(v & 0xffff_ffff)));
+        c.store(u64::MAX);
+        assert_eq!(c.into_inner(), u64::MAX);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn large_struct_goes_through_the_lock() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Big([u64; 4]);
+
+        assert!(!AtomicCell::<[u64; 4]>::is_lock_free());
+        let c = AtomicCell::new_locked(Big([0; 4]));
+        scope(|s| {
+            for t in 1..=4 {
+                let c = &c;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let old = c.swap(Big([t; 4]));
+                        assert!(old.0.iter().all(|&x| x == old.0[0]), "torn {old:?}");
+                    }
+                });
+            }
+        });
+        let Big(v) = c.load();
+        assert!(v.iter().all(|&x| x == v[0]));
+        c.store(Big([9; 4]));
+        assert_eq!(c.load(), Big([9; 4]));
+    }
+
+    #[test]
+    fn padded_struct_goes_through_the_lock() {
+        // As wide as a u32, with a padding byte after `a`
+        #[repr(C)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Padded {
+            a: u8,
+            b: u16,
+        }
+        assert!(is_lock_free::<Padded>());
+
+        let c = AtomicCell::new_locked(Padded { a: 1, b: 2 });
+        assert!(!c.atomic);
+        assert_eq!(c.swap(Padded { a: 3, b: 4 }), Padded { a: 1, b: 2 });
+        c.store(Padded { a: 5, b: 6 });
+        assert_eq!(c.load(), Padded { a: 5, b: 6 });
+
+        // Same size without padding takes the atomic
+        assert!(AtomicCell::new([1u16; 2]).atomic);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::{DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS};
+#[cfg(feature = "std")]
+use core::sync::atomic::Ordering;
+
+/// How a waiter of a [`Mutex`](crate::Mutex) waits between failed CAS
+/// attempts.
+///
+/// The mutex keeps one as a template and clones it for every acquisition that
+/// has to wait, then calls [`Backoff::reset`] once and [`Backoff::snooze`]
+/// after every failed CAS. Yielding to the scheduler and parking are handled
+/// by the mutex on top of this.
+pub trait Backoff: Clone {
+    /// Attempts after which [`Mutex::lock`](crate::Mutex::lock) calls
+    /// [`Backoff::on_spin_limit`] on every further one, see [`SpinLimit`].
+    /// A constant so the check costs a comparison, never reached by default.
+    const SPIN_LIMIT: usize = usize::MAX;
+
+    /// Starts over for a new acquisition.
+    fn reset(&mut self);
+
+    /// Waits a bit before the next attempt.
+    fn snooze(&mut self);
+
+    /// Called for every attempt past [`Backoff::SPIN_LIMIT`]. Does nothing by
+    /// default.
+    fn on_spin_limit(&mut self) {}
+
+    /// Whether a waiter past [`Backoff::SPIN_LIMIT`] parks on the mutex's wait
+    /// queue until a release wakes it, instead of calling
+    /// [`Backoff::on_spin_limit`]. False by default.
+    fn parks_at_spin_limit(&self) -> bool {
+        false
+    }
+}
+
+/// Spins for a number of iterations that doubles after every failure, from
+/// `initial` up to `max`. What [`Mutex::new`](crate::Mutex::new) uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultBackoff {
+    initial: u32,
+    max: u32,
+    spins: u32,
+}
+
+impl DefaultBackoff {
+    /// Backs off for `initial` spins at first and at most `max`.
+    pub const fn new(initial: u32, max: u32) -> Self {
+        Self {
+            initial,
+            max,
+            spins: initial,
+        }
+    }
+}
+
+impl Default for DefaultBackoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS)
+    }
+}
+
+impl Backoff for DefaultBackoff {
+    fn reset(&mut self) {
+        self.spins = self.initial;
+    }
+
+    fn snooze(&mut self) {
+        // A pure delay, it doesn't go through loom
+        for _ in 0..self.spins {
+            core::hint::spin_loop();
+        }
+        self.spins = self.spins.saturating_mul(2).min(self.max);
+    }
+}
+
+/// Retries the CAS straight away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    fn reset(&mut self) {}
+
+    fn snooze(&mut self) {}
+}
+
+/// Like [`DefaultBackoff`] but spins a random number of times within a window
+/// that doubles after every failure. Identical threads that keep colliding
+/// on the CAS drift apart instead of retrying in lockstep.
+///
+/// The randomness is a per-thread xorshift, nothing cryptographic.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitteredBackoff {
+    initial: u32,
+    max: u32,
+    window: u32,
+}
+
+#[cfg(feature = "std")]
+impl JitteredBackoff {
+    /// Starts with a window of `initial` spins that grows up to `max`.
+    pub const fn new(initial: u32, max: u32) -> Self {
+        Self {
+            initial,
+            max,
+            window: initial,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for JitteredBackoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Backoff for JitteredBackoff {
+    fn reset(&mut self) {
+        self.window = self.initial;
+    }
+
+    fn snooze(&mut self) {
+        for _ in 0..=random() % self.window.max(1) {
+            core::hint::spin_loop();
+        }
+        self.window = self.window.saturating_mul(2).min(self.max);
+    }
+}
+
+/// What [`SpinLimit`] does once a waiter is past its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnSpinLimit {
+    /// Panics, for code where waiting that long means something is broken.
+    /// A watchdog or test harness sees it as a panicked thread.
+    Panic,
+    /// Hands the time slice back to the scheduler on every further attempt.
+    Yield,
+    /// Parks the thread until the lock is released, like
+    /// [`MutexBuilder::park_after_spins`](crate::MutexBuilder::park_after_spins)
+    /// but with the bound in the type.
+    #[cfg(feature = "std")]
+    Park,
+}
+
+/// Bounds how long [`Mutex::lock`](crate::Mutex::lock) waits, with the bound
+/// fixed in the type as [`Backoff::SPIN_LIMIT`] so the check compiles down to
+/// a comparison against a constant. Attempts count every turn of the wait,
+/// spinning on a held lock included. Up to the limit it backs off like `B`,
+/// past it [`OnSpinLimit`] decides.
+///
+/// `SpinLimit<{ usize::MAX }>`, the default, never reaches its limit and
+/// behaves exactly like `B`. Any other limit makes every release check the
+/// wait queue, as [`OnSpinLimit::Park`] needs.
+///
+/// ```should_panic
+/// use mutex::{Mutex, OnSpinLimit, SpinLimit};
+///
+/// let m: Mutex<u32, SpinLimit<1000>> =
+///     Mutex::with_strategy(0, SpinLimit::new(OnSpinLimit::Panic));
+/// let _held = m.lock().unwrap();
+/// // Nobody will ever release it, the second lock panics instead of hanging
+/// m.with_lock(|_| {});
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinLimit<const LIMIT: usize = { usize::MAX }, B = DefaultBackoff> {
+    inner: B,
+    on_limit: OnSpinLimit,
+}
+
+impl<const LIMIT: usize> SpinLimit<LIMIT> {
+    /// Backs off like [`DefaultBackoff`] up to the limit.
+    pub const fn new(on_limit: OnSpinLimit) -> Self {
+        Self::with_backoff(
+            DefaultBackoff::new(DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS),
+            on_limit,
+        )
+    }
+}
+
+impl<const LIMIT: usize, B> SpinLimit<LIMIT, B> {
+    /// Backs off like `inner` up to the limit.
+    pub const fn with_backoff(inner: B, on_limit: OnSpinLimit) -> Self {
+        Self { inner, on_limit }
+    }
+}
+
+impl<const LIMIT: usize> Default for SpinLimit<LIMIT> {
+    fn default() -> Self {
+        Self::new(OnSpinLimit::Yield)
+    }
+}
+
+impl<const LIMIT: usize, B: Backoff> Backoff for SpinLimit<LIMIT, B> {
+    const SPIN_LIMIT: usize = LIMIT;
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn snooze(&mut self) {
+        self.inner.snooze();
+    }
+
+    fn on_spin_limit(&mut self) {
+        match self.on_limit {
+            OnSpinLimit::Panic => panic!("gave up on a Mutex after {LIMIT} attempts"),
+            OnSpinLimit::Yield => crate::sync::yield_now(),
+            // The mutex parks instead, see parks_at_spin_limit
+            #[cfg(feature = "std")]
+            OnSpinLimit::Park => {}
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn parks_at_spin_limit(&self) -> bool {
+        self.on_limit == OnSpinLimit::Park
+    }
+}
+
+#[cfg(feature = "std")]
+fn random() -> u32 {
+    use std::cell::Cell;
+    use std::sync::atomic::AtomicU32;
+
+    // Every thread gets a different odd seed, xorshift must never start at 0
+    static SEED: AtomicU32 = AtomicU32::new(0x9e37_79b9);
+    thread_local! {
+        static STATE: Cell<u32> = Cell::new(SEED.fetch_add(0x9e37_79b9, Ordering::Relaxed) | 1);
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+        x
+    })
+}
+
+// All but one share a Mutex between threads
+#[cfg(all(test, feature = "std", not(feature = "single-thread")))]
+mod tests {
+    use super::*;
+    use crate::Mutex;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread::{scope, yield_now};
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone, Default)]
+    struct Counting {
+        resets: Arc<AtomicUsize>,
+        snoozes: Arc<AtomicUsize>,
+    }
+
+    impl Backoff for Counting {
+        fn reset(&mut self) {
+            self.resets.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn snooze(&mut self) {
+            self.snoozes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn custom_backoff_snoozes_on_contention() {
+        let backoff = Counting::default();
+        let l = Mutex::with_strategy(0, backoff.clone());
+        for _ in 0..10 {
+            l.with_lock(|v| *v += 1);
+        }
+        // Never had to wait, so never even cloned
+        assert_eq!(backoff.resets.load(Ordering::Relaxed), 0);
+        assert_eq!(backoff.snoozes.load(Ordering::Relaxed), 0);
+
+        let waiting = Arc::new(AtomicUsize::new(0));
+        let w = waiting.clone();
+        l.on_contention(move || {
+            w.fetch_add(1, Ordering::Relaxed);
+        });
+        let guard = l.lock().unwrap();
+        scope(|s| {
+            s.spawn(|| l.with_lock(|v| *v += 1));
+            while waiting.load(Ordering::Relaxed) == 0 {
+                yield_now();
+            }
+            drop(guard);
+        });
+        assert_eq!(l.with_lock(|v| *v), 11);
+        assert_eq!(backoff.resets.load(Ordering::Relaxed), 1);
+        assert!(backoff.snoozes.load(Ordering::Relaxed) >= 1);
+    }
+
+    #[test]
+    fn no_backoff_counts_correctly() {
+        let l = Mutex::with_strategy(0, NoBackoff);
+        scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.into_inner(), 4 * 1000);
+    }
+
+    #[test]
+    fn jitter_differs_between_threads() {
+        let mine: Vec<u32> = (0..8).map(|_| random()).collect();
+        let theirs: Vec<u32> = std::thread::spawn(|| (0..8).map(|_| random()).collect())
+            .join()
+            .unwrap();
+        assert_ne!(mine, theirs);
+        assert!(mine.iter().all(|&x| x != 0));
+    }
+
+    // Spread of the mean time each thread waited for the lock
+    fn latency_variance<B: Backoff + Sync>(l: &Mutex<usize, B>) -> f64 {
+        const THREADS: usize = 8;
+        let means: Vec<f64> = scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut total = Duration::ZERO;
+                        for _ in 0..2000 {
+                            let start = Instant::now();
+                            let mut guard = l.lock().unwrap();
+                            total += start.elapsed();
+                            *guard += 1;
+                        }
+                        total.as_secs_f64() / 2000.0
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let avg = means.iter().sum::<f64>() / THREADS as f64;
+        means.iter().map(|m| (m - avg).powi(2)).sum::<f64>() / THREADS as f64
+    }
+
+    // Timing based and needs real parallelism, on one core the scheduler
+    // decides who waits. Run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn jitter_evens_out_latency() {
+        let fixed = latency_variance(&Mutex::with_strategy(0, DefaultBackoff::default()));
+        let jittered = latency_variance(&Mutex::with_strategy(0, JitteredBackoff::default()));
+        assert!(jittered < fixed, "jittered {jittered:e} vs fixed {fixed:e}");
+    }
+
+    #[test]
+    fn spin_limit_is_respected_under_a_held_lock() {
+        let l: Mutex<u32, SpinLimit<1000>> =
+            Mutex::with_strategy(0, SpinLimit::new(OnSpinLimit::Panic));
+        let guard = l.lock().unwrap();
+        let waited = scope(|s| s.spawn(|| l.with_lock(|v| *v += 1)).join());
+        // The watchdog's view: the waiter stopped instead of spinning forever
+        let panic = waited.unwrap_err();
+        assert_eq!(
+            panic.downcast_ref::<String>().unwrap(),
+            "gave up on a Mutex after 1000 attempts"
+        );
+        drop(guard);
+        // A short wait stays below the limit
+        scope(|s| {
+            let guard = l.lock().unwrap();
+            let waiter = s.spawn(|| l.with_lock(|v| *v += 1));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        assert_eq!(l.into_inner(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lets `n` threads wait for each other, reusable for any number of phases.
+pub struct Barrier {
+    n: usize,
+    // Threads that arrived in the current phase
+    count: AtomicUsize,
+    // Bumped by the last thread of every phase, the others wait for it to change
+    generation: AtomicUsize,
+}
+
+/// Returned by [`Barrier::wait`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Whether this thread was the one that completed the phase. Exactly one
+    /// thread per phase gets `true`.
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Barrier {
+    /// Creates a barrier that opens once `n` threads are waiting. A barrier
+    /// for 0 threads opens for every single one like one for 1.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n: if n == 0 { 1 } else { n },
+            count: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Spins until `n` threads have called `wait` in this phase, then lets all
+    /// of them through.
+    pub fn wait(&self) -> BarrierWaitResult {
+        // Read before arriving, the phase can't end until we've been counted
+        let generation = self.generation.load(Ordering::Acquire);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == self.n {
+            // Reset before opening, threads racing into the next phase only
+            // count once they've seen the new generation
+            self.count.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            return BarrierWaitResult { leader: true };
+        }
+        while self.generation.load(Ordering::Acquire) == generation {
+            // The last thread may not even be running yet
+            crate::sync::yield_now();
+        }
+        BarrierWaitResult { leader: false }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::thread::scope;
+
+    #[test]
+    fn nobody_crosses_early() {
+        const THREADS: usize = 8;
+        let barrier = Barrier::new(THREADS);
+        let phases = [AtomicUsize::new(0), AtomicUsize::new(0)];
+        let leaders = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for phase in &phases {
+                        phase.fetch_add(1, Ordering::Relaxed);
+                        if barrier.wait().is_leader() {
+                            leaders.fetch_add(1, Ordering::Relaxed);
+                        }
+                        // Everybody arrived before anybody got out
+                        assert_eq!(phase.load(Ordering::Relaxed), THREADS);
+                    }
+                });
+            }
+        });
+        assert_eq!(leaders.into_inner(), phases.len());
+    }
+
+    #[test]
+    fn single_thread_is_always_leader() {
+        for n in [0, 1] {
+            let barrier = Barrier::new(n);
+            assert!(barrier.wait().is_leader());
+            assert!(barrier.wait().is_leader());
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::sync::const_fn;
+use crate::{Mutex, DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS, YIELD_THRESHOLD};
+
+/// Configures how waiters of a [`Mutex`] spin, back off and park. The
+/// defaults build the same lock as [`Mutex::new`].
+///
+/// ```
+/// use mutex::MutexBuilder;
+///
+/// let m = MutexBuilder::new().spin_limit(10).with_backoff(false).build(0);
+/// m.with_lock(|v| *v += 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MutexBuilder {
+    spin_limit: usize,
+    backoff: bool,
+    park_after_spins: Option<usize>,
+}
+
+impl MutexBuilder {
+    /// Pure spinning with exponential backoff, yielding after
+    /// [`YIELD_THRESHOLD`] failed attempts.
+    pub const fn new() -> Self {
+        Self {
+            spin_limit: YIELD_THRESHOLD,
+            backoff: true,
+            park_after_spins: None,
+        }
+    }
+
+    /// Failed attempts after which a waiter gives its time slice back to the
+    /// scheduler on every retry instead of spinning.
+    pub const fn spin_limit(mut self, spins: usize) -> Self {
+        self.spin_limit = spins;
+        self
+    }
+
+    /// Whether waiters back off exponentially between CAS retries, see
+    /// [`Mutex::with_backoff`].
+    pub const fn with_backoff(mut self, backoff: bool) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Failed attempts after which a waiter parks until the holder unlocks.
+    /// `None`, the default, never parks. Every unlock of a parking mutex also
+    /// checks for sleepers, which costs a fence.
+    #[cfg(feature = "std")]
+    pub const fn park_after_spins(mut self, spins: Option<usize>) -> Self {
+        self.park_after_spins = spins;
+        self
+    }
+
+    const_fn! {
+        /// Creates an unlocked mutex holding `t` with this configuration.
+        pub const fn build<T>(self, t: T) -> Mutex<T> {
+            let (initial, max) = if self.backoff {
+                (DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS)
+            } else {
+                (0, 0)
+            };
+            Mutex::with_config(t, initial, max, self.spin_limit, self.park_after_spins)
+        }
+    }
+}
+
+impl Default for MutexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "single-thread"))]
+    use std::thread::scope;
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn low_spin_limit_still_counts() {
+        // Yields on the first failed retry, on one core that is what lets the
+        // holder run again
+        let l = MutexBuilder::new()
+            .spin_limit(1)
+            .with_backoff(false)
+            .build(0);
+        scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.into_inner(), 8 * 1000);
+    }
+
+    #[test]
+    fn defaults_match_new() {
+        assert_eq!(MutexBuilder::default(), MutexBuilder::new());
+        let l = MutexBuilder::new().build(5);
+        assert_eq!(l.with_lock(|v| *v), 5);
+    }
+}
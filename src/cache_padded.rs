@@ -0,0 +1,68 @@
+use core::ops::{Deref, DerefMut};
+
+/// Pads and aligns `T` to a cache line so neighbours don't share it.
+///
+/// Two mutexes on the same line ping-pong it between cores even when every
+/// thread uses its own mutex (false sharing). `[CachePadded<Mutex<T>>; N]`
+/// keeps each of them on a line of its own.
+// x86_64 and aarch64 prefetch lines in pairs, so pad to 128 there
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    /// Wraps `value`.
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    /// Unwraps the value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::Mutex;
+    use std::mem::{align_of, size_of};
+
+    #[test]
+    fn neighbours_dont_share_a_line() {
+        assert!(align_of::<CachePadded<u8>>() >= 64);
+        let locks: [CachePadded<Mutex<u8>>; 2] = Default::default();
+        let a = &locks[0] as *const _ as usize;
+        let b = &locks[1] as *const _ as usize;
+        assert!(b - a >= 64);
+        assert_eq!(b - a, size_of::<CachePadded<Mutex<u8>>>());
+        locks[1].with_lock(|v| *v += 1);
+        assert_eq!(locks[1].with_lock(|v| *v), 1);
+    }
+}
@@ -0,0 +1,87 @@
+// C bindings over RawSpinMutex, declared in include/mutex_raw.h. The lock is
+// opaque to C and only ever handled through the pointer mutex_raw_new returns.
+use crate::RawSpinMutex;
+use lock_api::RawMutex;
+
+/// Allocates a new unlocked lock, free it with [`mutex_raw_free`].
+#[no_mangle]
+pub extern "C" fn mutex_raw_new() -> *mut RawSpinMutex {
+    Box::into_raw(Box::new(RawSpinMutex::INIT))
+}
+
+/// Spins until the lock is acquired.
+///
+/// # Safety
+///
+/// `m` must come from [`mutex_raw_new`] and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mutex_raw_lock(m: *const RawSpinMutex) {
+    unsafe { (*m).lock() }
+}
+
+/// Takes the lock if it is free, returns whether it did. A strong CAS, so it
+/// only fails when the lock is held, never spuriously.
+///
+/// # Safety
+///
+/// `m` must come from [`mutex_raw_new`] and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mutex_raw_try_lock(m: *const RawSpinMutex) -> bool {
+    unsafe { (*m).try_lock() }
+}
+
+/// Releases the lock.
+///
+/// # Safety
+///
+/// `m` must come from [`mutex_raw_new`] and not be freed yet, and be locked
+/// by the calling thread.
+#[no_mangle]
+pub unsafe extern "C" fn mutex_raw_unlock(m: *const RawSpinMutex) {
+    unsafe { (*m).unlock() }
+}
+
+/// Frees a lock, null is ignored.
+///
+/// # Safety
+///
+/// `m` must be null or come from [`mutex_raw_new`] and not be freed yet. It
+/// must be unlocked and no other thread may still use it.
+#[no_mangle]
+pub unsafe extern "C" fn mutex_raw_free(m: *mut RawSpinMutex) {
+    if !m.is_null() {
+        drop(unsafe { Box::from_raw(m) });
+    }
+}
+
+// Shared's own Sync impl would get past single-thread's missing one
+#[cfg(all(test, not(feature = "single-thread")))]
+mod tests {
+    use super::*;
+    use std::thread::scope;
+
+    #[test]
+    fn shared_between_threads() {
+        struct Shared(*mut RawSpinMutex, std::cell::UnsafeCell<usize>);
+        unsafe impl Sync for Shared {}
+
+        let shared = Shared(mutex_raw_new(), std::cell::UnsafeCell::new(0));
+        scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    // The closure would capture the two fields on their own
+                    let shared = &shared;
+                    for _ in 0..1000 {
+                        unsafe {
+                            mutex_raw_lock(shared.0);
+                            *shared.1.get() += 1;
+                            mutex_raw_unlock(shared.0);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(shared.1.into_inner(), 4000);
+        unsafe { mutex_raw_free(shared.0) };
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{Condvar, Mutex, PoisonError};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Creates a bounded channel holding at most `capacity` items, 0 is treated
+/// as 1. [`Sender::send`] blocks while it is full and [`Receiver::recv`]
+/// while it is empty.
+///
+/// Built from the crate's own pieces: a [`Mutex`] around a `VecDeque` and one
+/// [`Condvar`] for each of the two conditions.
+///
+/// ```
+/// # #[cfg(not(feature = "single-thread"))] {
+/// let (tx, rx) = mutex::channel(4);
+/// std::thread::spawn(move || {
+///     for i in 0..10 {
+///         tx.send(i).unwrap();
+///     }
+/// });
+/// // None once the queue is drained and every sender is gone
+/// let received: Vec<_> = std::iter::from_fn(|| rx.recv()).collect();
+/// assert_eq!(received, (0..10).collect::<Vec<_>>());
+/// # }
+/// ```
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            capacity: capacity.max(1),
+            senders: 1,
+            receiver: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // Signalled on every push and when the last sender goes away
+    not_empty: Condvar,
+    // Signalled on every pop and when the receiver goes away
+    not_full: Condvar,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver: bool,
+}
+
+/// Sending half of a [`channel`], clone it for more producers.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Waits for room and pushes `t`. Fails, handing `t` back, once the
+    /// receiver is gone.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let guard = self
+            .shared
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut state = self
+            .shared
+            .not_full
+            .wait_while(guard, |s| s.receiver && s.items.len() >= s.capacity)
+            .unwrap_or_else(PoisonError::into_inner);
+        if !state.receiver {
+            return Err(SendError(t));
+        }
+        state.items.push_back(t);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.with_lock(|s| s.senders += 1);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let last = self.shared.state.with_lock(|s| {
+            s.senders -= 1;
+            s.senders == 0
+        });
+        // Blocked receivers have to find out there is nothing more coming
+        if last {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+/// Receiving half of a [`channel`]. Not `Clone`, but [`recv`](Self::recv)
+/// takes `&self` so several scoped threads can share one.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for an item and pops it. `None` once the channel is empty and
+    /// every sender has been dropped.
+    pub fn recv(&self) -> Option<T> {
+        let guard = self
+            .shared
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut state = self
+            .shared
+            .not_empty
+            .wait_while(guard, |s| s.senders > 0 && s.items.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        let item = state.items.pop_front();
+        drop(state);
+        if item.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        item
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.with_lock(|s| s.receiver = false);
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Returned by [`Sender::send`] when the receiver is gone, holds the item
+/// that couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a channel whose receiver is gone")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "single-thread"))]
+    use std::thread::scope;
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn producers_and_consumers_move_every_item() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 500;
+        let (tx, rx) = channel(8);
+        let sums = scope(|s| {
+            for p in 0..PRODUCERS {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        tx.send(p * PER_PRODUCER + i).unwrap();
+                    }
+                });
+            }
+            // The consumers only stop once every sender, this one included, is gone
+            drop(tx);
+            let consumers: Vec<_> = (0..3)
+                .map(|_| {
+                    let rx = &rx;
+                    s.spawn(move || {
+                        let (mut n, mut sum) = (0, 0);
+                        while let Some(v) = rx.recv() {
+                            n += 1;
+                            sum += v;
+                        }
+                        (n, sum)
+                    })
+                })
+                .collect();
+            consumers
+                .into_iter()
+                .map(|c| c.join().unwrap())
+                .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1))
+        });
+        let total = PRODUCERS * PER_PRODUCER;
+        assert_eq!(sums, (total, total * (total - 1) / 2));
+    }
+
+    #[test]
+    fn send_fails_without_a_receiver() {
+        let (tx, rx) = channel(1);
+        tx.send(1).unwrap();
+        drop(rx);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn capacity_bounds_the_queue() {
+        let (tx, rx) = channel(2);
+        scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    tx.send(i).unwrap();
+                }
+            });
+            for i in 0..100 {
+                assert!(rx.shared.state.with_lock(|s| s.items.len()) <= 2);
+                assert_eq!(rx.recv(), Some(i));
+            }
+        });
+    }
+}
@@ -0,0 +1,207 @@
+use crate::YIELD_THRESHOLD;
+use std::any::Any;
+use std::cell::{Cell, UnsafeCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+// Passes over the request list a combiner makes before letting go, so one
+// unlucky thread doesn't end up serving everybody else forever
+const MAX_COMBINE_ROUNDS: usize = 8;
+
+// A published operation. Lives on the stack of the thread that published it,
+// which doesn't return before `done` is set, so the combiner can always
+// reach it until then
+struct Request<T> {
+    // Takes the closure out of `op` and runs it
+    run: unsafe fn(*mut (), &mut T),
+    op: *mut (),
+    next: Cell<*mut Request<T>>,
+    // Set by whoever ran the operation, the request must not be touched after
+    done: AtomicBool,
+    panic: UnsafeCell<Option<Box<dyn Any + Send>>>,
+}
+
+/// A lock for tiny, very hot critical sections such as a shared counter.
+///
+/// Instead of every thread taking the lock in turn, threads publish their
+/// operation and whoever gets the lock runs all the published ones before
+/// releasing it. The protected value stays in one cache, most threads never
+/// touch it or the lock at all.
+///
+/// Operations from different threads run in no particular order, one thread's
+/// operations in the order it applied them.
+pub struct FlatCombiningMutex<T> {
+    locked: AtomicBool,
+    // Stack of published requests, pushed by publishers and emptied in one
+    // swap by the combiner
+    pending: AtomicPtr<Request<T>>,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for FlatCombiningMutex<T> where T: Send {}
+
+impl<T> FlatCombiningMutex<T> {
+    /// Creates a new unlocked mutex holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            pending: AtomicPtr::new(ptr::null_mut()),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Runs `op` on the protected value, either on this thread or on the one
+    /// holding the lock, and returns once it is done. `op` is `Send` since it
+    /// may run on another thread.
+    ///
+    /// A panic in `op` resumes on the calling thread once the combiner caught
+    /// it, the other operations are unaffected. There is no poisoning.
+    pub fn apply<F: FnOnce(&mut T) + Send>(&self, op: F) {
+        unsafe fn run<T, F: FnOnce(&mut T)>(op: *mut (), t: &mut T) {
+            let op = unsafe { (*op.cast::<Option<F>>()).take() };
+            op.expect("request ran twice")(t);
+        }
+
+        let mut op = Some(op);
+        let request = Request {
+            run: run::<T, F>,
+            op: (&mut op as *mut Option<F>).cast(),
+            next: Cell::new(ptr::null_mut()),
+            done: AtomicBool::new(false),
+            panic: UnsafeCell::new(None),
+        };
+        self.publish(&request);
+
+        let mut spins = 0;
+        while !request.done.load(Ordering::Acquire) {
+            if self.locked.load(Ordering::Relaxed)
+                || self
+                    .locked
+                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+            {
+                // Somebody is combining and will most likely get to us
+                spins += 1;
+                if spins > YIELD_THRESHOLD {
+                    std::thread::yield_now();
+                } else {
+                    std::hint::spin_loop();
+                }
+                continue;
+            }
+            // Published before we locked, so this runs our request too
+            self.combine();
+            self.locked.store(false, Ordering::Release);
+        }
+
+        // `done` was seen with Acquire, the combiner is finished with the request
+        if let Some(payload) = request.panic.into_inner() {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    fn publish(&self, request: &Request<T>) {
+        let node = request as *const Request<T> as *mut Request<T>;
+        let mut head = self.pending.load(Ordering::Relaxed);
+        loop {
+            request.next.set(head);
+            // Release hands the request's fields to the combiner
+            match self.pending.compare_exchange_weak(
+                head,
+                node,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    // Only called with the lock held
+    fn combine(&self) {
+        let v = unsafe { &mut *self.v.get() };
+        for _ in 0..MAX_COMBINE_ROUNDS {
+            let mut node = self.pending.swap(ptr::null_mut(), Ordering::Acquire);
+            if node.is_null() {
+                return;
+            }
+            while let Some(request) = unsafe { node.as_ref() } {
+                // Read before `done`, the publisher may return right after
+                node = request.next.get();
+                let ran = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                    (request.run)(request.op, v)
+                }));
+                if let Err(payload) = ran {
+                    unsafe { *request.panic.get() = Some(payload) };
+                }
+                request.done.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Mutable access without locking, `&mut self` already guarantees
+    /// exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.v.get_mut()
+    }
+
+    /// Consumes the mutex and returns the value.
+    pub fn into_inner(self) -> T {
+        self.v.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::scope;
+
+    #[test]
+    fn counter() {
+        let l = FlatCombiningMutex::new(0u64);
+        scope(|s| {
+            for _ in 0..32 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        l.apply(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.into_inner(), 32 * 500);
+    }
+
+    #[test]
+    fn operations_can_borrow_the_callers_stack() {
+        let l = FlatCombiningMutex::new(Vec::new());
+        scope(|s| {
+            for i in 0..8 {
+                let l = &l;
+                s.spawn(move || {
+                    let mut seen = 0;
+                    for j in 0..100 {
+                        l.apply(|v| {
+                            v.push(i * 100 + j);
+                            seen = v.len();
+                        });
+                        assert!(seen > 0);
+                    }
+                });
+            }
+        });
+        let mut v = l.into_inner();
+        v.sort();
+        assert_eq!(v, (0..800).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panic_goes_back_to_its_caller() {
+        let l = FlatCombiningMutex::new(0);
+        let r = panic::catch_unwind(AssertUnwindSafe(|| l.apply(|_| panic!("boom"))));
+        assert_eq!(*r.unwrap_err().downcast::<&str>().unwrap(), "boom");
+        l.apply(|v| *v += 1);
+        assert_eq!(l.into_inner(), 1);
+    }
+}
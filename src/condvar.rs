@@ -0,0 +1,130 @@
+use crate::waiters::WaitQueue;
+use crate::{Backoff, LockResult, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Condition variable to wait on while holding a [`Mutex`](crate::Mutex).
+///
+/// Like std's, waits can wake up spuriously so always wait in a loop that
+/// checks the condition.
+pub struct Condvar {
+    // Bumped by every notify, a waiter that sees it change knows it may have missed
+    // a notification and returns instead of parking again
+    seq: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the lock, parks until notified and takes the lock again.
+    #[track_caller]
+    pub fn wait<'a, T: ?Sized, B: Backoff>(
+        &self,
+        guard: MutexGuard<'a, T, B>,
+    ) -> LockResult<MutexGuard<'a, T, B>> {
+        let mutex = guard.mutex();
+        let seq = self.seq.load(Ordering::Acquire);
+        // Get in the queue before unlocking, a notify sent right after we let go of the
+        // lock must find us. If it unparks us before we park, park returns immediately
+        self.waiters.register();
+        drop(guard);
+        while self.seq.load(Ordering::Acquire) == seq {
+            thread::park();
+        }
+        self.waiters.deregister();
+        mutex.lock()
+    }
+
+    /// Releases the lock and parks until `condition` returns false, taking the
+    /// lock again for every check.
+    #[track_caller]
+    pub fn wait_while<'a, T: ?Sized, B: Backoff>(
+        &self,
+        mut guard: MutexGuard<'a, T, B>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockResult<MutexGuard<'a, T, B>> {
+        while condition(&mut guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// Wakes up one waiting thread.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+
+    /// Wakes up every waiting thread.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.waiters.wake_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(feature = "single-thread")))]
+mod tests {
+    use super::*;
+    use crate::Mutex;
+    use std::collections::VecDeque;
+    use std::thread::scope;
+
+    #[test]
+    fn bounded_queue() {
+        const CAPACITY: usize = 2;
+        let queue = Mutex::new(VecDeque::new());
+        let not_empty = Condvar::new();
+        let not_full = Condvar::new();
+
+        scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    let mut q = not_full
+                        .wait_while(queue.lock().unwrap(), |q| q.len() == CAPACITY)
+                        .unwrap();
+                    q.push_back(i);
+                    not_empty.notify_one();
+                }
+            });
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                let mut q = not_empty
+                    .wait_while(queue.lock().unwrap(), |q| q.is_empty())
+                    .unwrap();
+                assert!(q.len() <= CAPACITY);
+                received.push(q.pop_front().unwrap());
+                not_full.notify_one();
+            }
+            assert_eq!(received, (0..100).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn notify_all_wakes_everyone() {
+        let ready = Mutex::new(false);
+        let cv = Condvar::new();
+        scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let guard = cv.wait_while(ready.lock().unwrap(), |r| !*r).unwrap();
+                    assert!(*guard);
+                });
+            }
+            *ready.lock().unwrap() = true;
+            cv.notify_all();
+        });
+    }
+}
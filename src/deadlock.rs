@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Ids are handed out the first time a mutex is locked rather than when it is
+// created, `Mutex::new` is const and can't bump a counter. 0 means not yet
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    // Ids of the mutexes this thread holds, in locking order
+    static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies a mutex for the lock order check. Locks must always be taken in
+/// increasing id order.
+pub(crate) struct LockId(AtomicUsize);
+
+impl LockId {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        let id = self.0.load(Ordering::Relaxed);
+        if id != 0 {
+            return id;
+        }
+        let fresh = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        // Another thread may have numbered it first, theirs wins
+        match self
+            .0
+            .compare_exchange(0, fresh, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            Ok(_) => fresh,
+            Err(id) => id,
+        }
+    }
+
+    /// Called before waiting for the lock. Panics if this thread already holds
+    /// a mutex with a higher id, another thread taking the two in the other
+    /// order would deadlock with us.
+    #[track_caller]
+    pub(crate) fn check(&self) {
+        let id = self.get();
+        HELD.with(|held| {
+            if let Some(&higher) = held.borrow().iter().find(|&&h| h > id) {
+                panic!("lock order inversion: locking mutex #{id} while holding mutex #{higher}");
+            }
+        });
+    }
+
+    pub(crate) fn acquired(&self) {
+        let id = self.get();
+        HELD.with(|held| held.borrow_mut().push(id));
+    }
+
+    pub(crate) fn released(&self) {
+        let id = self.0.load(Ordering::Relaxed);
+        // Guards can be dropped out of order. A guard moved to another thread
+        // is released there and isn't on this thread's stack
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(i) = held.iter().rposition(|&h| h == id) {
+                held.remove(i);
+            }
+        });
+    }
+}
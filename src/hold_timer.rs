@@ -0,0 +1,60 @@
+use core::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Times critical sections of a Mutex against a threshold, see
+// Mutex::warn_if_held_longer_than. Off until a threshold is set, and then the
+// clock is only read on acquisition and release
+pub(crate) struct HoldTimer {
+    // Nanoseconds, 0 while off
+    threshold: AtomicU64,
+    // When the current holder got the lock. Only the holder touches it, the
+    // lock itself orders the accesses
+    since: UnsafeCell<Option<Instant>>,
+    slow: AtomicUsize,
+}
+
+unsafe impl Sync for HoldTimer {}
+
+impl HoldTimer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            threshold: AtomicU64::new(0),
+            since: UnsafeCell::new(None),
+            slow: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn set_threshold(&self, threshold: Option<Duration>) {
+        // A zero threshold would be off, every hold takes at least a nanosecond
+        let nanos = threshold.map_or(0, |t| t.as_nanos().clamp(1, u64::MAX as u128) as u64);
+        self.threshold.store(nanos, Ordering::Relaxed);
+    }
+
+    // The caller must hold the lock
+    #[inline]
+    pub(crate) unsafe fn start(&self) {
+        if self.threshold.load(Ordering::Relaxed) != 0 {
+            *self.since.get() = Some(Instant::now());
+        }
+    }
+
+    // The caller must hold the lock, and call this before releasing it. Returns
+    // how long it was held if that was too long
+    #[inline]
+    pub(crate) unsafe fn stop(&self) -> Option<Duration> {
+        // Empty unless the threshold was already set when the lock was taken
+        let since = (*self.since.get()).take()?;
+        let held = since.elapsed();
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        if threshold != 0 && held.as_nanos() > threshold as u128 {
+            self.slow.fetch_add(1, Ordering::Relaxed);
+            return Some(held);
+        }
+        None
+    }
+
+    pub(crate) fn slow(&self) -> usize {
+        self.slow.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,178 @@
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex as OsMutex, MutexGuard as OsMutexGuard, TryLockError};
+
+// Spins before handing the wait over to the OS by default
+const DEFAULT_SPIN_LIMIT: usize = 100;
+
+/// A lock that spins briefly on `try_lock` and then blocks in
+/// [`std::sync::Mutex`]. Uncontended acquisition costs one CAS, and heavy
+/// load or long critical sections put waiters to sleep instead of burning CPU.
+///
+/// The inner OS mutex only guards `()`. Holding it is what grants access to
+/// the value in the `UnsafeCell`, so both paths exclude each other. Unlike
+/// [`Mutex`](crate::Mutex) there is no poisoning.
+pub struct HybridMutex<T> {
+    os: OsMutex<()>,
+    spin_limit: usize,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for HybridMutex<T> where T: Send {}
+
+impl<T> HybridMutex<T> {
+    /// Creates a new unlocked mutex holding `t` that spins 100 times before
+    /// blocking.
+    pub const fn new(t: T) -> Self {
+        Self::with_spin_limit(t, DEFAULT_SPIN_LIMIT)
+    }
+
+    /// Creates a new unlocked mutex that spins `spin_limit` times before
+    /// blocking a waiter.
+    pub const fn with_spin_limit(t: T, spin_limit: usize) -> Self {
+        Self {
+            os: OsMutex::new(()),
+            spin_limit,
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Acquires the lock, blocking the thread if spinning didn't get it.
+    pub fn lock(&self) -> HybridMutexGuard<'_, T> {
+        for _ in 0..self.spin_limit {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            std::hint::spin_loop();
+        }
+        // A panicking holder poisons the OS mutex but it guards nothing, the
+        // value itself is only ever reached through a live guard
+        let os = self.os.lock().unwrap_or_else(|e| e.into_inner());
+        HybridMutexGuard {
+            mutex: self,
+            _os: os,
+        }
+    }
+
+    /// Attempts to take the lock without spinning or blocking.
+    #[must_use]
+    pub fn try_lock(&self) -> Option<HybridMutexGuard<'_, T>> {
+        let os = match self.os.try_lock() {
+            Ok(os) => os,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        Some(HybridMutexGuard {
+            mutex: self,
+            _os: os,
+        })
+    }
+
+    /// Acquires the lock, runs `f` on the protected value and releases it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Returns a mutable reference to the value, no locking needed since the
+    /// borrow is exclusive.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.v.get_mut()
+    }
+
+    /// Consumes the mutex and returns the value.
+    pub fn into_inner(self) -> T {
+        self.v.into_inner()
+    }
+}
+
+impl<T: Default> Default for HybridMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// RAII guard returned by [`HybridMutex::lock`]. Dropping it releases the
+/// OS mutex, which wakes a blocked waiter if there is one.
+#[must_use = "if unused the lock is released immediately"]
+pub struct HybridMutexGuard<'a, T> {
+    mutex: &'a HybridMutex<T>,
+    // Already !Send, so the guard is too
+    _os: OsMutexGuard<'a, ()>,
+}
+
+unsafe impl<T> Sync for HybridMutexGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for HybridMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T> DerefMut for HybridMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::{scope, sleep};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn counter() {
+        let l = HybridMutex::with_spin_limit(0, 10);
+        scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.into_inner(), 16 * 1000);
+    }
+
+    #[test]
+    fn panic_in_holder_does_not_wedge_the_lock() {
+        let l = HybridMutex::new(0);
+        scope(|s| {
+            let _ = s.spawn(|| l.with_lock(|_| panic!("holder failed"))).join();
+        });
+        assert_eq!(l.with_lock(|v| *v + 1), 1);
+    }
+
+    // utime + stime of the calling thread in clock ticks
+    #[cfg(target_os = "linux")]
+    fn cpu_ticks() -> u64 {
+        let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+        // The command name can contain spaces, fields are counted after it
+        let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+        fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn waiter_sleeps_through_a_long_hold() {
+        let l = HybridMutex::new(0);
+        let guard = l.lock();
+        scope(|s| {
+            let waiter = s.spawn(|| {
+                let (start, ticks) = (Instant::now(), cpu_ticks());
+                l.with_lock(|v| *v += 1);
+                (start.elapsed(), cpu_ticks() - ticks)
+            });
+            sleep(Duration::from_millis(100));
+            drop(guard);
+            let (waited, ticks) = waiter.join().unwrap();
+            assert!(waited >= Duration::from_millis(100));
+            // A tick is usually 10ms, a spinning waiter would have used all ten
+            assert!(ticks <= 2, "waiter used {ticks} ticks of CPU");
+        });
+        assert_eq!(l.into_inner(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+//! Implementation of Mutex using spin loop approach and memory ordering in Rust
+//!
+//! ```
+//! use mutex::Mutex;
+//!
+//! let m = Mutex::new(0);
+//! m.with_lock(|v| *v += 1);
+//! assert_eq!(m.with_lock(|v| *v), 1);
+//!
+//! let mut guard = m.lock().unwrap();
+//! *guard += 1;
+//! assert_eq!(*guard, 2);
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// Never ever use spin locks :), this implemetation is experimental
+// Why?, read this - https://matklad.github.io/2020/01/02/spinlocks-considered-harmful.html
+
+#[cfg(feature = "std")]
+mod adaptive;
+mod atomic_cell;
+mod backoff;
+mod barrier;
+mod builder;
+mod cache_padded;
+#[cfg(all(feature = "capi", not(loom)))]
+mod capi;
+#[cfg(feature = "std")]
+mod channel;
+#[cfg(feature = "std")]
+mod combining;
+#[cfg(feature = "std")]
+mod condvar;
+#[cfg(feature = "deadlock-detection")]
+mod deadlock;
+#[cfg(feature = "std")]
+mod hold_timer;
+#[cfg(feature = "std")]
+mod hybrid;
+#[cfg(feature = "std")]
+mod mcs;
+mod mutex;
+mod once;
+mod poison;
+#[cfg(all(feature = "lock_api", not(loom)))]
+mod raw;
+#[cfg(feature = "std")]
+mod reentrant;
+#[cfg(feature = "std")]
+mod reorder;
+mod rwlock;
+mod semaphore;
+mod seqlock;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "stats")]
+mod stats;
+mod sync;
+mod ticket;
+#[cfg(feature = "std")]
+mod waiters;
+#[cfg(feature = "async")]
+mod wakers;
+
+#[cfg(feature = "std")]
+pub use adaptive::{AdaptiveMutex, AdaptiveMutexGuard};
+pub use atomic_cell::{AtomicCell, NoPadding};
+#[cfg(feature = "std")]
+pub use backoff::JitteredBackoff;
+pub use backoff::{Backoff, DefaultBackoff, NoBackoff, OnSpinLimit, SpinLimit};
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use builder::MutexBuilder;
+pub use cache_padded::CachePadded;
+#[cfg(all(feature = "capi", not(loom)))]
+pub use capi::{
+    mutex_raw_free, mutex_raw_lock, mutex_raw_new, mutex_raw_try_lock, mutex_raw_unlock,
+};
+#[cfg(feature = "std")]
+pub use channel::{channel, Receiver, SendError, Sender};
+#[cfg(feature = "std")]
+pub use combining::FlatCombiningMutex;
+#[cfg(feature = "std")]
+pub use condvar::Condvar;
+#[cfg(feature = "std")]
+pub use hybrid::{HybridMutex, HybridMutexGuard};
+#[cfg(feature = "std")]
+pub use mcs::{McsGuard, McsMutex};
+#[cfg(feature = "std")]
+pub use mutex::ArcMutexGuard;
+#[cfg(feature = "async")]
+pub use mutex::AsyncMutexGuard;
+pub use mutex::{
+    lock2, lock2_retry, MappedMutexGuard, Mutex, MutexGuard, RawGuard, RawLock,
+    DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS, YIELD_THRESHOLD,
+};
+pub use once::Once;
+pub use poison::{LockResult, PoisonError, WouldBlock};
+#[cfg(all(feature = "lock_api", not(loom)))]
+pub use raw::RawSpinMutex;
+#[cfg(feature = "std")]
+pub use reentrant::{ReentrantMutex, ReentrantMutexGuard};
+#[cfg(feature = "std")]
+pub use reorder::run_reorder_experiment;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use seqlock::SeqLock;
+#[cfg(feature = "std")]
+pub use sharded::ShardedMutex;
+#[cfg(feature = "stats")]
+pub use stats::{MutexStats, WAIT_BUCKETS};
+pub use ticket::{TicketMutex, TicketMutexGuard};
@@ -0,0 +1,154 @@
+use crate::YIELD_THRESHOLD;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+// One per waiting thread. Each waiter spins on its *own* `locked` flag so the
+// line being hammered stays in that core's cache, the only cross-core traffic is
+// the hand over from the previous holder
+struct QNode {
+    locked: AtomicBool,
+    next: AtomicPtr<QNode>,
+}
+
+/// MCS queue lock, waiters form a linked list and each spins on its own node.
+/// Scales better than [`Mutex`](crate::Mutex) and
+/// [`TicketMutex`](crate::TicketMutex) when many threads contend, and is fair.
+pub struct McsMutex<T> {
+    // Last node in the queue, null when nobody holds the lock
+    tail: AtomicPtr<QNode>,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for McsMutex<T> where T: Send {}
+
+impl<T> McsMutex<T> {
+    /// Creates a new unlocked mutex holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Joins the queue and spins until the previous holder hands over the lock.
+    pub fn lock(&self) -> McsGuard<'_, T> {
+        // The node lives on the heap so its address doesn't change when the guard
+        // moves, our successor holds a pointer to it until we release
+        let node = Box::into_raw(Box::new(QNode {
+            locked: AtomicBool::new(true),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        // Release publishes the node's initial state to whoever links behind us,
+        // Acquire syncs with the previous holder if the queue was empty
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        if !prev.is_null() {
+            // prev can't be freed before it sees us in `next`, see unlock
+            unsafe { (*prev).next.store(node, Ordering::Release) };
+            let mut spins = 0;
+            while unsafe { (*node).locked.load(Ordering::Acquire) } {
+                // Same problem as the ticket lock, only our predecessor can let us in
+                // so if it got preempted spinning just delays it further
+                spins += 1;
+                if spins > YIELD_THRESHOLD {
+                    std::thread::yield_now();
+                } else {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+        McsGuard {
+            mutex: self,
+            node,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquires the lock, runs `f` on the protected value and releases it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+}
+
+/// RAII guard returned by [`McsMutex::lock`], owns this thread's queue node.
+#[must_use = "if unused the lock is released immediately"]
+pub struct McsGuard<'a, T> {
+    mutex: &'a McsMutex<T>,
+    node: *mut QNode,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for McsGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for McsGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T> DerefMut for McsGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+impl<T> Drop for McsGuard<'_, T> {
+    fn drop(&mut self) {
+        let node = self.node;
+        unsafe {
+            let mut next = (*node).next.load(Ordering::Acquire);
+            if next.is_null() {
+                // Nobody linked behind us yet, if we are still the tail the queue is empty
+                if self
+                    .mutex
+                    .tail
+                    .compare_exchange(node, ptr::null_mut(), Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    drop(Box::from_raw(node));
+                    return;
+                }
+                // Someone swapped themselves into the tail but hasn't written our `next`
+                // yet, wait for it. Freeing the node now would have them write to freed memory
+                loop {
+                    next = (*node).next.load(Ordering::Acquire);
+                    if !next.is_null() {
+                        break;
+                    }
+                    // It's between its swap and its store, if it got preempted there
+                    // only the scheduler can help
+                    std::thread::yield_now();
+                }
+            }
+            // The successor frees its own node, we must not touch it after this store
+            (*next).locked.store(false, Ordering::Release);
+            drop(Box::from_raw(node));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::scope;
+
+    #[test]
+    fn counter() {
+        let l = McsMutex::new(0);
+        scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.with_lock(|v| *v), 16 * 1000);
+        assert!(l.tail.load(Ordering::Relaxed).is_null());
+    }
+}
@@ -0,0 +1,3151 @@
+use crate::backoff::{Backoff, DefaultBackoff};
+#[cfg(feature = "deadlock-detection")]
+use crate::deadlock::LockId;
+#[cfg(feature = "std")]
+use crate::hold_timer::HoldTimer;
+use crate::poison::{LockResult, PoisonError, WouldBlock};
+#[cfg(feature = "std")]
+use crate::rwlock::RwLock;
+#[cfg(feature = "stats")]
+use crate::stats::{MutexStats, Stats};
+#[cfg(feature = "debug-locks")]
+use crate::sync::AtomicPtr;
+use crate::sync::{self, const_fn, AtomicBool, Ordering, UnsafeCell};
+#[cfg(feature = "std")]
+use crate::waiters::WaitQueue;
+#[cfg(feature = "async")]
+use crate::wakers::WakerQueue;
+use core::fmt;
+#[cfg(feature = "async")]
+use core::future::Future;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+#[cfg(feature = "debug-locks")]
+use core::{panic::Location, ptr};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(all(feature = "debug-locks", feature = "std"))]
+use std::thread::ThreadId;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+const LOCKED: bool = true;
+const UNLOCKED: bool = false;
+
+/// Number of spins a waiter backs off for after its first failed retry.
+pub const DEFAULT_INITIAL_SPINS: u32 = 4;
+/// Cap on the exponential backoff between retries.
+pub const DEFAULT_MAX_SPINS: u32 = 256;
+/// Failed attempts after which a waiter starts yielding to the OS scheduler
+/// instead of spinning.
+pub const YIELD_THRESHOLD: usize = 100;
+// Failed attempts between clock reads in try_lock_until, Instant::now costs
+// far more than a CAS
+#[cfg(feature = "std")]
+const DEADLINE_CHECK_INTERVAL: usize = 32;
+
+// What lock passes to Mutex::wait, it never gives up
+const UNBOUNDED: Option<fn(usize) -> bool> = None;
+
+/// A spin lock protecting a value of type `T`.
+///
+/// `T` can be unsized, a `Box<Mutex<Concrete>>` coerces to
+/// `Box<Mutex<dyn Trait>>` like any other box.
+///
+/// `B` decides how waiters back off between attempts, see [`Backoff`].
+///
+/// Sharing the lock between threads hands `T` from one to the other, so the
+/// mutex is only `Sync` (and `Send`) when `T: Send`. A `Mutex<Rc<_>>` stays
+/// on its thread.
+pub struct Mutex<T: ?Sized, B = DefaultBackoff> {
+    locked: AtomicBool,
+    poisoned: AtomicBool,
+    // Completed critical sections, bumped by every release
+    generation: sync::AtomicUsize,
+    // Cloned for every acquisition that has to wait
+    backoff: B,
+    // Failed attempts before waiters start yielding, YIELD_THRESHOLD unless built
+    yield_after: usize,
+    // Failed attempts before waiters park on `parked`, None to only ever spin
+    #[cfg(feature = "std")]
+    park_after: Option<usize>,
+    #[cfg(feature = "std")]
+    parked: WaitQueue,
+    // B has a SPIN_LIMIT, which may park on `parked` so releases have to wake it
+    #[cfg(feature = "std")]
+    spin_limited: bool,
+    // Success ordering of the acquiring CAS and ordering of the releasing store
+    acquire: Ordering,
+    release: Ordering,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+    #[cfg(feature = "std")]
+    on_contention: RwLock<Option<ContentionHook>>,
+    #[cfg(feature = "std")]
+    hold_timer: HoldTimer,
+    // Call site of the current holder, null while unlocked
+    #[cfg(feature = "debug-locks")]
+    held_at: AtomicPtr<Location<'static>>,
+    // Thread of the current holder, null while unlocked
+    #[cfg(all(feature = "debug-locks", feature = "std"))]
+    holder: AtomicPtr<ThreadId>,
+    #[cfg(feature = "deadlock-detection")]
+    id: LockId,
+    // Set by new_named, only kept where something reports it
+    #[cfg(any(feature = "debug-locks", feature = "tracing", feature = "metrics"))]
+    name: Option<&'static str>,
+    // Thread that took the lock last, to catch force_unlock from the wrong one
+    #[cfg(all(debug_assertions, feature = "std"))]
+    owner: sync::AtomicUsize,
+    // Tasks waiting in async_lock, woken one at a time on unlock
+    #[cfg(feature = "async")]
+    wakers: WakerQueue,
+    v: UnsafeCell<T>,
+}
+
+/// Declares `static` mutexes, initialized at compile time with
+/// [`Mutex::new`].
+///
+/// ```
+/// # #[cfg(not(feature = "single-thread"))] {
+/// use mutex::{spinlock, Mutex};
+///
+/// spinlock! {
+///     static COUNTER: Mutex<u64> = 0;
+///     /// Attributes and visibility are kept
+///     pub(crate) static NAMES: Mutex<Vec<&'static str>> = Vec::new();
+/// }
+///
+/// COUNTER.with_lock(|v| *v += 1);
+/// NAMES.with_lock(|v| v.push("spin"));
+/// assert_eq!(COUNTER.with_lock(|v| *v), 1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spinlock {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)*) => {
+        $($(#[$attr])* $vis static $name: $ty = $crate::Mutex::new($init);)*
+    };
+}
+
+#[cfg(feature = "std")]
+type ContentionHook = Box<dyn Fn() + Send + Sync>;
+
+// Waiters clone `backoff` through a shared reference. With single-thread the
+// flags are plain cells and the mutex must stay on one thread
+#[cfg(not(feature = "single-thread"))]
+unsafe impl<T: ?Sized, B: Sync> Sync for Mutex<T, B> where T: Send {}
+
+// Breaks the build if a new field ever takes away Send or Sync for T: Send,
+// or Sync from a guard of a T: Sync
+#[cfg(not(feature = "single-thread"))]
+const _: () = {
+    fn assert_send_sync<M: ?Sized + Send + Sync>() {}
+    #[allow(dead_code)]
+    fn check<T: ?Sized + Send>() {
+        assert_send_sync::<Mutex<T>>();
+        assert_send_sync::<Mutex<T, crate::NoBackoff>>();
+    }
+    // Guards stay shareable, only moving them is ruled out (see MutexGuard)
+    fn assert_sync<M: ?Sized + Sync>() {}
+    #[allow(dead_code)]
+    fn check_guard<'a, T: ?Sized + Sync + 'a>() {
+        assert_sync::<MutexGuard<'a, T>>();
+    }
+    // The owned guard is the one that may change threads
+    #[cfg(feature = "std")]
+    #[allow(dead_code)]
+    fn check_arc_guard<T: ?Sized + Send>() {
+        fn assert_send<M: ?Sized + Send>() {}
+        assert_send::<ArcMutexGuard<T>>();
+    }
+};
+
+// Still movable to another thread, only sharing one is ruled out
+#[cfg(feature = "single-thread")]
+const _: () = {
+    fn assert_send<M: ?Sized + Send>() {}
+    #[allow(dead_code)]
+    fn check<T: ?Sized + Send>() {
+        assert_send::<Mutex<T>>();
+    }
+};
+
+impl<T> Mutex<T> {
+    const_fn! {
+        /// Creates a new unlocked mutex holding `t`.
+        pub const fn new(t: T) -> Self {
+            Self::with_backoff(t, DEFAULT_INITIAL_SPINS, DEFAULT_MAX_SPINS)
+        }
+    }
+
+    const_fn! {
+        /// Creates a new unlocked mutex whose waiters back off for `initial_spins`
+        /// before retrying the CAS, doubling on every failure up to `max_spins`.
+        /// `with_backoff(t, 0, 0)` disables the backoff.
+        pub const fn with_backoff(t: T, initial_spins: u32, max_spins: u32) -> Self {
+            Self::with_strategy(t, DefaultBackoff::new(initial_spins, max_spins))
+        }
+    }
+
+    const_fn! {
+        /// Creates a new unlocked mutex that acquires with `acquire` and releases
+        /// with `release` instead of Acquire/Release. Weakening them breaks the
+        /// lock, this is here to experiment with exactly that, e.g.
+        /// `Mutex::new_with_ordering(t, Ordering::Relaxed, Ordering::Relaxed)`.
+        ///
+        /// # Panics
+        ///
+        /// If `release` is `Acquire` or `AcqRel`, which a store can't use.
+        pub const fn new_with_ordering(t: T, acquire: Ordering, release: Ordering) -> Self {
+            assert!(
+                !matches!(release, Ordering::Acquire | Ordering::AcqRel),
+                "release ordering must be valid for a store"
+            );
+            let mut m = Self::new(t);
+            m.acquire = acquire;
+            m.release = release;
+            m
+        }
+    }
+
+    const_fn! {
+        /// Creates a new unlocked mutex with a name for diagnostics, shown by
+        /// `Debug`, the `tracing` spans and as the `name` label of the
+        /// `metrics` feature.
+        ///
+        /// The name is only stored with the `debug-locks`, `tracing` or
+        /// `metrics` feature, otherwise it is dropped and [`Mutex::name`]
+        /// returns `None`.
+        pub const fn new_named(t: T, name: &'static str) -> Self {
+            #[cfg(any(feature = "debug-locks", feature = "tracing", feature = "metrics"))]
+            {
+                let mut m = Self::new(t);
+                m.name = Some(name);
+                m
+            }
+            #[cfg(not(any(feature = "debug-locks", feature = "tracing", feature = "metrics")))]
+            {
+                let _ = name;
+                Self::new(t)
+            }
+        }
+    }
+
+    const_fn! {
+        // See MutexBuilder::build
+        pub(crate) const fn with_config(
+            t: T,
+            initial_spins: u32,
+            max_spins: u32,
+            yield_after: usize,
+            park_after: Option<usize>,
+        ) -> Self {
+            let mut m = Self::with_backoff(t, initial_spins, max_spins);
+            m.yield_after = yield_after;
+            #[cfg(feature = "std")]
+            {
+                m.park_after = park_after;
+            }
+            #[cfg(not(feature = "std"))]
+            let _ = park_after;
+            m
+        }
+    }
+
+    /// Creates a new unlocked mutex holding `t`, already wrapped in an [`Arc`]
+    /// for sharing between threads and for [`Mutex::lock_arc`].
+    #[cfg(feature = "std")]
+    pub fn new_arc(t: T) -> Arc<Self> {
+        Arc::new(Self::new(t))
+    }
+}
+
+impl<T, B: Backoff> Mutex<T, B> {
+    const_fn! {
+        /// Creates a new unlocked mutex whose waiters back off with `backoff`.
+        ///
+        /// ```
+        /// use mutex::{Mutex, NoBackoff};
+        ///
+        /// let m = Mutex::with_strategy(0, NoBackoff);
+        /// m.with_lock(|v| *v += 1);
+        /// ```
+        pub const fn with_strategy(t: T, backoff: B) -> Self {
+            Self {
+                locked: AtomicBool::new(UNLOCKED),
+                poisoned: AtomicBool::new(false),
+                generation: sync::AtomicUsize::new(0),
+                backoff,
+                yield_after: YIELD_THRESHOLD,
+                #[cfg(feature = "std")]
+                park_after: None,
+                #[cfg(feature = "std")]
+                parked: WaitQueue::new(),
+                #[cfg(feature = "std")]
+                spin_limited: B::SPIN_LIMIT != usize::MAX,
+                acquire: Ordering::Acquire,
+                release: Ordering::Release,
+                #[cfg(feature = "stats")]
+                stats: Stats::new(),
+                #[cfg(feature = "std")]
+                on_contention: RwLock::new(None),
+                #[cfg(feature = "std")]
+                hold_timer: HoldTimer::new(),
+                #[cfg(feature = "debug-locks")]
+                held_at: AtomicPtr::new(ptr::null_mut()),
+                #[cfg(all(feature = "debug-locks", feature = "std"))]
+                holder: AtomicPtr::new(ptr::null_mut()),
+                #[cfg(feature = "deadlock-detection")]
+                id: LockId::new(),
+                #[cfg(any(feature = "debug-locks", feature = "tracing", feature = "metrics"))]
+                name: None,
+                #[cfg(all(debug_assertions, feature = "std"))]
+                owner: sync::AtomicUsize::new(0),
+                #[cfg(feature = "async")]
+                wakers: WakerQueue::new(),
+                v: UnsafeCell::new(t),
+            }
+        }
+    }
+
+    /// Consumes the mutex and returns the data. Owning it means nobody else
+    /// can hold the lock so there is nothing to acquire.
+    pub fn into_inner(self) -> T {
+        self.v.into_inner()
+    }
+
+    /// Stores `new` and returns the previous value, under a single lock.
+    /// Ignores poisoning like [`Mutex::with_lock`].
+    #[track_caller]
+    pub fn replace(&self, new: T) -> T {
+        self.with_lock(|v| core::mem::replace(v, new))
+    }
+
+    /// Takes the value out, leaving `T::default()` in its place.
+    #[track_caller]
+    pub fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.with_lock(core::mem::take)
+    }
+
+    /// Replaces the value with `f(old)`, moving it out and back in under one
+    /// lock.
+    ///
+    /// While `f` runs the mutex holds `T::default()`. Nobody else can see it
+    /// since the lock is held, but if `f` panics the default is what stays
+    /// behind (and the mutex is poisoned).
+    #[track_caller]
+    pub fn update(&self, f: impl FnOnce(T) -> T)
+    where
+        T: Default,
+    {
+        self.with_lock(|v| *v = f(core::mem::take(v)));
+    }
+}
+
+impl<T: ?Sized, B: Backoff> Mutex<T, B> {
+    /// Spins until the lock is acquired and returns a guard that releases it
+    /// when dropped.
+    ///
+    /// With the `deadlock-detection` feature this panics if the thread already
+    /// holds a mutex that was first locked after this one. Some other thread
+    /// locking them in the usual order could deadlock with it.
+    ///
+    /// Fails with a [`PoisonError`] if a previous holder panicked, the lock is
+    /// acquired either way.
+    ///
+    /// With the `tracing` feature a contended acquisition spins inside a
+    /// trace level `wait` span, its `spins` field records how many attempts
+    /// it took and an `acquired` event ends it.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T, B>> {
+        #[cfg(feature = "deadlock-detection")]
+        self.id.check();
+        // Nobody else could ever release it, waiting would hang forever
+        #[cfg(all(feature = "single-thread", debug_assertions))]
+        assert!(!self.is_locked(), "Mutex locked twice with single-thread");
+        // Unbounded, only returns once the lock is ours
+        self.wait(UNBOUNDED);
+
+        // Only ever written while holding the lock, the CAS's Acquire already makes
+        // the previous holder's write visible
+        let guard = MutexGuard::new(self);
+        if self.poisoned.load(Ordering::Relaxed) {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    // The wait behind lock and the bounded try_lock variants, so they all back
+    // off, yield and hit SPIN_LIMIT the same way and count attempts alike.
+    // `give_up` sees the attempt count after every turn, returns false once it
+    // says so. A bounded wait never parks, nothing would wake it at its bound
+    #[inline]
+    fn wait(&self, mut give_up: Option<impl FnMut(usize) -> bool>) -> bool {
+        // Only cloned once we have to wait, the fast path doesn't pay for it
+        let mut backoff = None;
+        let mut attempts = 0;
+        #[cfg(feature = "stats")]
+        let mut failed_cas = 0;
+        // Only opened once we have to wait, closed when the CAS finally succeeds
+        #[cfg(feature = "tracing")]
+        let mut wait = None;
+        while !self.try_acquire() {
+            #[cfg(feature = "stats")]
+            {
+                failed_cas += 1;
+            }
+
+            if attempts == 0 {
+                #[cfg(feature = "tracing")]
+                {
+                    wait = Some(
+                        tracing::trace_span!(
+                            "wait",
+                            name = self.name(),
+                            spins = tracing::field::Empty
+                        )
+                        .entered(),
+                    );
+                }
+                self.contended();
+            }
+
+            // If the holder got descheduled spinning can't make progress, it only burns
+            // the time slice the holder needs to finish. Past the threshold hand the CPU
+            // back to the scheduler on every failure instead
+            attempts += 1;
+            // Parking only pays off while the lock is held, the loop below does it
+            let _ = self.check_spin_limit(&mut backoff, attempts);
+            if give_up.as_mut().is_some_and(|f| f(attempts)) {
+                return false;
+            }
+            if attempts > self.yield_after {
+                sync::yield_now();
+            }
+
+            // MESI protocol: stay in S when locked
+            // Prevents threads from frequntly executing compare_exchange_weak which
+            // requires exclusive access thereby leading to high contention
+            while self.locked.load(Ordering::Relaxed) == LOCKED {
+                // Waiting here counts too, a preempted holder keeps us in this loop
+                attempts += 1;
+                let park = self.check_spin_limit(&mut backoff, attempts);
+                if give_up.as_mut().is_some_and(|f| f(attempts)) {
+                    return false;
+                }
+                #[cfg(not(feature = "std"))]
+                let _ = park;
+                #[cfg(feature = "std")]
+                if give_up.is_none() && (park || self.park_after.is_some_and(|n| attempts > n)) {
+                    self.park();
+                    continue;
+                }
+                if attempts > self.yield_after {
+                    sync::yield_now();
+                } else {
+                    // PAUSE on x86, YIELD on ARM. Tells the core we are busy waiting so it
+                    // can back off the pipeline and give a sibling hyperthread a chance
+                    sync::spin_loop();
+                }
+            }
+
+            // Thundering herd: once the holder releases, every waiter sees UNLOCKED at the
+            // same time and they all race on the CAS. Waiting a bit longer after each failure
+            // spreads the retries out so fewer of them fight over the cache line
+            backoff.get_or_insert_with(|| self.fresh_backoff()).snooze();
+
+            // The read loop above left the line in S. The CAS needs it in M, ask for it
+            // now so the upgrade overlaps with the branch back to try_acquire
+            sync::prefetch_write(&self.locked);
+
+            // Why compare_exchange_weak? (unless built with strong-cas, see try_acquire)
+            // x86: CAS (Compare and Swap)
+            // ARM: LDREX STREX - Load, Link and Store conditional
+
+            // LDREX takes exclusive ownership of the value (to read or modify)
+            // STREX stores the value only if there isn't another exclusive
+            // ownership
+
+            // - compare_exchange: impl using a loop of LDREX and STREX
+            //  while LDREX is true
+            //     while current state has changed e.g to UNLOCKED // ensure current state has changed to UNLOCK otherwise fail to execute STREX
+            //                  STREX the new value
+            // (simply means don't change this value if other threads are using it)
+            // the inner loop ensure state is not changed before STREX is executed so
+            // we ensure we are within the thread
+            // This is cheap but costly in terms of regsitry pressure
+
+            // - compare_exchange_weak: This option is offered so that instead of creating nested loop
+            // for ARM, we can use compare_exchange_weak which is both compatible with ARM and x64, it also
+            // supports spurious failure meaning anything that prevents swap to fail including previous state
+            // not changed e.g from LOCKED to UNLOCKED or other reasons
+        }
+        // No need to store LOCKED here, the successful CAS already wrote it. A CAS is a
+        // single read-modify-write so nobody can slip in between us reading UNLOCKED and
+        // writing LOCKED, and its Acquire pairs with the Release in the guard's drop.
+        // That's all ownership needs
+
+        #[cfg(feature = "stats")]
+        self.stats.record(failed_cas);
+        #[cfg(feature = "metrics")]
+        self.record_metrics(attempts);
+        #[cfg(feature = "tracing")]
+        if let Some(wait) = wait {
+            wait.record("spins", attempts);
+            tracing::trace!("acquired");
+        }
+        true
+    }
+
+    // Sleeps until an unlock wakes us, or returns straight away if the lock was
+    // released while we got in the queue
+    #[cfg(feature = "std")]
+    #[cold]
+    fn park(&self) {
+        self.parked.register();
+        // Pairs with the fence in wake_parked. Either the unlock sees us queued
+        // or we see it unlocked, never neither
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        if self.locked.load(Ordering::Relaxed) == LOCKED {
+            std::thread::park();
+        }
+        self.parked.deregister();
+    }
+
+    // A comparison against a constant, optimized out unless the backoff sets
+    // a SPIN_LIMIT. True if the backoff wants to park instead
+    #[inline]
+    fn check_spin_limit(&self, backoff: &mut Option<B>, attempts: usize) -> bool {
+        if attempts <= B::SPIN_LIMIT {
+            return false;
+        }
+        let backoff = backoff.get_or_insert_with(|| self.fresh_backoff());
+        if backoff.parks_at_spin_limit() {
+            return true;
+        }
+        backoff.on_spin_limit();
+        false
+    }
+
+    fn fresh_backoff(&self) -> B {
+        let mut backoff = self.backoff.clone();
+        backoff.reset();
+        backoff
+    }
+
+    // Slow path only, an uncontended lock never looks at the hook
+    #[cold]
+    fn contended(&self) {
+        #[cfg(feature = "std")]
+        if let Some(hook) = &*self.on_contention.read() {
+            hook();
+        }
+    }
+
+    /// Registers `f` to be called whenever [`Mutex::lock`] finds the lock
+    /// taken, once per acquisition that had to wait. Replaces any previous
+    /// hook.
+    ///
+    /// `f` runs on the waiting thread before it starts spinning. It must not
+    /// call `on_contention` on the same mutex.
+    #[cfg(feature = "std")]
+    pub fn on_contention(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.on_contention.write() = Some(Box::new(f));
+    }
+
+    /// Counts every critical section that lasts longer than `threshold`, see
+    /// [`Mutex::slow_holds`], and with the `tracing` feature also emits a
+    /// warning naming the mutex. `None` turns it off again.
+    ///
+    /// Off by default, and then nothing reads the clock. Once set, every
+    /// acquisition and release reads it once. Only locks taken after the call
+    /// are timed.
+    ///
+    /// ```
+    /// use mutex::Mutex;
+    /// use std::time::Duration;
+    ///
+    /// let m = Mutex::new(0);
+    /// m.warn_if_held_longer_than(Some(Duration::from_millis(1)));
+    /// m.with_lock(|_| std::thread::sleep(Duration::from_millis(5)));
+    /// assert_eq!(m.slow_holds(), 1);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn warn_if_held_longer_than(&self, threshold: Option<Duration>) {
+        self.hold_timer.set_threshold(threshold);
+    }
+
+    /// How many critical sections exceeded the threshold of
+    /// [`Mutex::warn_if_held_longer_than`].
+    #[cfg(feature = "std")]
+    pub fn slow_holds(&self) -> usize {
+        self.hold_timer.slow()
+    }
+
+    /// Like [`Mutex::lock`], but the guard keeps its own clone of the `Arc`
+    /// instead of borrowing the mutex. It has no lifetime, so it can be returned
+    /// from a function or moved into a spawned thread.
+    ///
+    /// ```
+    /// # #[cfg(not(feature = "single-thread"))] {
+    /// use mutex::Mutex;
+    ///
+    /// let m = Mutex::new_arc(0);
+    /// let mut guard = m.lock_arc().unwrap();
+    /// std::thread::spawn(move || *guard += 1).join().unwrap();
+    /// assert_eq!(m.with_lock(|v| *v), 1);
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[track_caller]
+    pub fn lock_arc(self: &Arc<Self>) -> LockResult<ArcMutexGuard<T, B>> {
+        let to_arc = |guard: MutexGuard<'_, T, B>| {
+            let panicking = guard.panicking;
+            core::mem::forget(guard);
+            // The guard may be dropped on another thread, so stop tracking it here
+            #[cfg(feature = "deadlock-detection")]
+            self.id.released();
+            ArcMutexGuard {
+                mutex: Arc::clone(self),
+                panicking,
+            }
+        };
+        match self.lock() {
+            Ok(guard) => Ok(to_arc(guard)),
+            Err(e) => Err(PoisonError::new(to_arc(e.into_inner()))),
+        }
+    }
+
+    /// Waits for the lock without blocking the thread. While the lock is held
+    /// the task's waker is queued and the future returns
+    /// [`Poll::Pending`], every unlock wakes the longest waiting task. Works
+    /// with any executor.
+    ///
+    /// Like [`Mutex::with_lock`] this ignores poisoning.
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let m = mutex::Mutex::new(0);
+    /// *m.async_lock().await += 1;
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn async_lock(&self) -> impl Future<Output = AsyncMutexGuard<'_, T, B>> {
+        AsyncLock {
+            mutex: self,
+            waker: None,
+        }
+    }
+
+    /// Attempts to take the lock with a single CAS, returns `None` if it is
+    /// held by someone else. Never spins.
+    ///
+    /// This doesn't report poisoning, check [`Mutex::is_poisoned`] if needed.
+    #[must_use]
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, B>> {
+        self.guard_if(self.try_acquire())
+    }
+
+    /// [`Mutex::try_lock`] with a single `compare_exchange_weak`, whatever
+    /// the `strong-cas` feature says.
+    ///
+    /// The weak CAS may fail spuriously, so this can return `None` even though
+    /// the lock was free. Meant for callers that retry anyway, on LL/SC targets
+    /// like ARM it saves the inner loop a strong CAS needs. On x86 both compile
+    /// to the same instruction.
+    #[must_use]
+    #[track_caller]
+    pub fn try_lock_weak(&self) -> Option<MutexGuard<'_, T, B>> {
+        let r =
+            self.locked
+                .compare_exchange_weak(UNLOCKED, LOCKED, self.acquire, Ordering::Relaxed);
+        self.guard_if(r.is_ok())
+    }
+
+    /// [`Mutex::try_lock`] with a single `compare_exchange`, whatever the
+    /// `strong-cas` feature says. Returns `None` only if the lock was held.
+    #[must_use]
+    #[track_caller]
+    pub fn try_lock_strong(&self) -> Option<MutexGuard<'_, T, B>> {
+        let r = self
+            .locked
+            .compare_exchange(UNLOCKED, LOCKED, self.acquire, Ordering::Relaxed);
+        self.guard_if(r.is_ok())
+    }
+
+    // The rest of the try_lock family once the CAS is done. Not a closure,
+    // #[track_caller] doesn't see through them
+    #[track_caller]
+    fn guard_if(&self, acquired: bool) -> Option<MutexGuard<'_, T, B>> {
+        if !acquired {
+            return None;
+        }
+        #[cfg(feature = "stats")]
+        self.stats.record(0);
+        #[cfg(feature = "metrics")]
+        self.record_metrics(0);
+        Some(MutexGuard::new(self))
+    }
+
+    // Only named mutexes report, an unnamed one has no label to tell it apart
+    // and skips the recorder lookup entirely
+    #[cfg(feature = "metrics")]
+    fn record_metrics(&self, spins: usize) {
+        if let Some(name) = self.name {
+            metrics::counter!("mutex.acquisitions", "name" => name).increment(1);
+            metrics::histogram!("mutex.wait_spins", "name" => name).record(spins as f64);
+        }
+    }
+
+    // The one CAS every acquisition goes through. Weak by default, see the long
+    // comment in wait. With strong-cas it can't fail spuriously, which saves a
+    // retry on x86 where both compile to the same instruction anyway and makes
+    // try_lock on a free lock always succeed
+    #[inline]
+    fn try_acquire(&self) -> bool {
+        #[cfg(not(feature = "strong-cas"))]
+        let r =
+            self.locked
+                .compare_exchange_weak(UNLOCKED, LOCKED, self.acquire, Ordering::Relaxed);
+        #[cfg(feature = "strong-cas")]
+        let r = self
+            .locked
+            .compare_exchange(UNLOCKED, LOCKED, self.acquire, Ordering::Relaxed);
+        r.is_ok()
+    }
+
+    /// Spins and backs off like [`Mutex::lock`] but gives up after `dur`,
+    /// returning `None`. Never parks, even where `lock` would. Ignores
+    /// poisoning like [`Mutex::try_lock`].
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[track_caller]
+    pub fn try_lock_for(&self, dur: Duration) -> Option<MutexGuard<'_, T, B>> {
+        match Instant::now().checked_add(dur) {
+            Some(deadline) => self.try_lock_until(deadline),
+            // Too far in the future to represent, same as no deadline
+            None => Some(self.lock().unwrap_or_else(PoisonError::into_inner)),
+        }
+    }
+
+    /// Like [`Mutex::try_lock_for`] with an absolute `deadline`. The clock is
+    /// only read every few attempts, so it can overshoot by a few spins.
+    #[cfg(feature = "std")]
+    #[must_use]
+    #[track_caller]
+    pub fn try_lock_until(&self, deadline: Instant) -> Option<MutexGuard<'_, T, B>> {
+        let deadline_passed =
+            |attempts| attempts % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline;
+        if self.wait(Some(deadline_passed)) {
+            Some(MutexGuard::new(self))
+        } else {
+            None
+        }
+    }
+
+    /// Waits like [`Mutex::lock`] for up to `max_spins` attempts, counted the
+    /// same way as [`Backoff::SPIN_LIMIT`], and fails with [`WouldBlock`]
+    /// instead of waiting any longer. Never parks. `try_lock_spins(1)` is
+    /// [`Mutex::try_lock`]. Ignores poisoning like `try_lock`.
+    ///
+    /// Unlike [`Mutex::try_lock_for`] it never reads the clock, so it also
+    /// works without `std`.
+    #[track_caller]
+    pub fn try_lock_spins(&self, max_spins: usize) -> Result<MutexGuard<'_, T, B>, WouldBlock> {
+        if max_spins == 0 {
+            return Err(WouldBlock);
+        }
+        if self.wait(Some(|attempts| attempts >= max_spins)) {
+            Ok(MutexGuard::new(self))
+        } else {
+            Err(WouldBlock)
+        }
+    }
+
+    /// Spins until the lock is acquired, runs `f` on the protected value and
+    /// releases the lock.
+    ///
+    /// A poisoned lock is entered anyway, a panic in `f` still poisons it.
+    /// The guard releases the lock while unwinding, so a panic never leaves it
+    /// held.
+    ///
+    /// With the `tracing` feature `f` runs inside a `held` span, next to the
+    /// `wait` span [`Mutex::lock`] opens while spinning.
+    #[track_caller]
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut guard = self.lock().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "tracing")]
+        let _held = tracing::trace_span!("held", name = self.name()).entered();
+        f(&mut guard)
+    }
+
+    /// Like [`Mutex::with_lock`] but gives up with `None` instead of spinning
+    /// when the lock is contended.
+    #[track_caller]
+    pub fn try_with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.try_lock().map(|mut guard| f(&mut guard))
+    }
+
+    /// Whether the lock is currently held.
+    ///
+    /// The answer can be stale by the time it is returned, another thread may
+    /// lock or unlock right after the load. Only use it for heuristics and
+    /// assertions, never to decide whether it is safe to touch the data.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed) == LOCKED
+    }
+
+    /// Spins until the lock is observed free, without taking it.
+    ///
+    /// Racy by design: another thread can lock it again right after this
+    /// returns, so it says nothing about the state afterwards. Meant for
+    /// orchestrating tests, e.g. waiting for a holder to finish before
+    /// checking its effects, not for deciding whether the data is safe to
+    /// touch. Yields like [`lock`](Self::lock) once spinning goes on for long.
+    ///
+    /// ```
+    /// # #[cfg(not(feature = "single-thread"))] {
+    /// use mutex::Mutex;
+    ///
+    /// let m = Mutex::new(0);
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| m.with_lock(|v| *v += 1));
+    ///     // Free before or after the other thread's turn, this can't tell
+    ///     m.wait_until_unlocked();
+    /// });
+    /// # }
+    /// ```
+    pub fn wait_until_unlocked(&self) {
+        let mut attempts = 0;
+        while self.is_locked() {
+            attempts += 1;
+            if attempts > self.yield_after {
+                sync::yield_now();
+            } else {
+                sync::spin_loop();
+            }
+        }
+    }
+
+    /// Releases a lock whose guard was forgotten, e.g. with
+    /// [`MutexGuard::leak`]. Poisoning is left as it is.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held and its guard must be gone without having
+    /// released it, and no reference obtained through that guard may be used
+    /// afterwards. Unlocking while a live guard exists lets a second thread
+    /// in next to it.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the mutex isn't locked or was locked by another
+    /// thread.
+    pub unsafe fn force_unlock(&self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        assert!(
+            !self.is_locked() || self.owner.load(Ordering::Relaxed) == sync::thread_token(),
+            "force_unlock called from a thread that didn't lock the mutex"
+        );
+        #[cfg(feature = "deadlock-detection")]
+        self.id.released();
+        #[cfg(feature = "debug-locks")]
+        self.held_at.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(all(feature = "debug-locks", feature = "std"))]
+        self.holder.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(feature = "std")]
+        end_hold(&self.hold_timer, self.name());
+        release(&self.locked, &self.generation, self.release);
+        #[cfg(feature = "std")]
+        wake_parked(self.parked());
+        #[cfg(feature = "async")]
+        wake_async(&self.wakers);
+    }
+
+    /// Where the current holder took the lock, `None` while it is unlocked.
+    ///
+    /// Meant for a watchdog thread looking for a stuck lock, the holder can
+    /// release it right after this returns.
+    ///
+    /// ```
+    /// let m = mutex::Mutex::new(0);
+    /// let guard = m.lock().unwrap();
+    /// assert_eq!(m.held_at().unwrap().line(), line!() - 1);
+    /// drop(guard);
+    /// assert!(m.held_at().is_none());
+    /// ```
+    #[cfg(feature = "debug-locks")]
+    pub fn held_at(&self) -> Option<&'static Location<'static>> {
+        // Only ever set from Location::caller(), which is 'static
+        unsafe { self.held_at.load(Ordering::Relaxed).as_ref() }
+    }
+
+    /// The thread that took the lock, `None` while it is unlocked.
+    ///
+    /// Like [`held_at`](Self::held_at) it is meant for a watchdog that wants
+    /// to name whoever is sitting on a contended lock, and can be stale by the
+    /// time it returns. A guard that moved to another thread, such as an
+    /// [`ArcMutexGuard`], still reports the thread that locked.
+    ///
+    /// ```
+    /// let m = mutex::Mutex::new(0);
+    /// let guard = m.lock().unwrap();
+    /// assert_eq!(m.owner(), Some(std::thread::current().id()));
+    /// drop(guard);
+    /// assert_eq!(m.owner(), None);
+    /// ```
+    #[cfg(all(feature = "debug-locks", feature = "std"))]
+    pub fn owner(&self) -> Option<ThreadId> {
+        // Only ever set from current_thread_id(), which is 'static
+        unsafe { self.holder.load(Ordering::Relaxed).as_ref().copied() }
+    }
+
+    // Global order lock2 takes mutexes in
+    fn order_key(&self) -> usize {
+        #[cfg(feature = "deadlock-detection")]
+        return self.id.get();
+        #[cfg(not(feature = "deadlock-detection"))]
+        return self as *const Self as *const () as usize;
+    }
+
+    // Numbers the mutex now instead of on its first lock, for containers that
+    // always lock their mutexes in a fixed order
+    #[cfg(feature = "deadlock-detection")]
+    pub(crate) fn assign_lock_id(&self) {
+        self.id.get();
+    }
+
+    /// How many times the lock has been released since the mutex was
+    /// created, wrapping on overflow.
+    ///
+    /// An advanced primitive for code that works through
+    /// [`data_ptr`](Self::data_ptr) or [`MutexGuard::leak`]. Every release
+    /// bumps it with a Release store after the holder's last write, and this
+    /// read is Acquire: once it returns `n`, the writes of the first `n`
+    /// critical sections are visible.
+    ///
+    /// To check that no critical section ended during an observation, the ABA
+    /// case a plain `is_locked` check can't see, do what a seqlock reader does:
+    /// read the count, read the data, put a
+    /// [`fence(Acquire)`](core::sync::atomic::fence) before reading the count
+    /// again, and compare the two. The count only moves when a critical
+    /// section ends, so this can't see one that is still running. A reader
+    /// that can't rule that out some other way has to take the lock.
+    ///
+    /// ```
+    /// use mutex::Mutex;
+    ///
+    /// let m = Mutex::new(0);
+    /// let before = m.generation();
+    /// m.with_lock(|v| *v += 1);
+    /// assert_eq!(m.generation(), before + 1);
+    /// ```
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Whether a thread panicked while holding the lock.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Marks the data as consistent again after a panic.
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::Relaxed);
+    }
+
+    /// Contention counters since creation or the last
+    /// [`reset_stats`](Mutex::reset_stats), same as
+    /// [`snapshot_stats`](Mutex::snapshot_stats).
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> MutexStats {
+        self.snapshot_stats()
+    }
+
+    /// Both contention counters as of the same moment. An acquisition or a
+    /// reset racing with the read is either fully in the snapshot or not at
+    /// all, so the pair never mixes values from before and after it.
+    #[cfg(feature = "stats")]
+    pub fn snapshot_stats(&self) -> MutexStats {
+        self.stats.get()
+    }
+
+    /// How many acquisitions failed 0, 1-9, 10-99, 100-999 and 1000 or more
+    /// CAS attempts before getting the lock, see
+    /// [`WAIT_BUCKETS`](crate::WAIT_BUCKETS). Easier to read than the raw
+    /// [`total_spins`](MutexStats::total_spins): a lock that is mostly free
+    /// with rare long waits looks very different from one that always waits
+    /// a little.
+    ///
+    /// Each bucket is read on its own, the five counts aren't a snapshot of
+    /// the same moment like [`snapshot_stats`](Mutex::snapshot_stats).
+    #[cfg(feature = "stats")]
+    pub fn wait_histogram(&self) -> [usize; 5] {
+        self.stats.waits()
+    }
+
+    /// Zeroes the contention counters and the wait histogram.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Raw pointer to the protected data.
+    ///
+    /// Dereferencing it is only sound while holding the lock, otherwise
+    /// another thread may be writing through its guard at the same time.
+    ///
+    /// ```
+    /// use mutex::Mutex;
+    ///
+    /// let m = Mutex::new(5);
+    /// let guard = m.lock().unwrap();
+    /// // We hold the lock, nobody else can touch the data
+    /// let v = unsafe { std::ptr::read_volatile(m.data_ptr()) };
+    /// assert_eq!(v, 5);
+    /// drop(guard);
+    /// ```
+    pub fn data_ptr(&self) -> *mut T {
+        self.v.get()
+    }
+
+    /// Mutable access to the data without locking, `&mut self` already
+    /// guarantees exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.v.get_mut()
+    }
+
+    // No from_mut(&mut T) -> &mut Mutex<T> to go with it. That cast is only
+    // sound for a layout-transparent wrapper like Cell, here `locked` and the
+    // rest of the state sit next to `v` and a &mut T has no room for them
+
+    /// Mutable access to the data without locking or checking anything.
+    ///
+    /// # Safety
+    ///
+    /// For as long as the returned reference lives no other reference to the
+    /// data may exist or be created. That means no guard, on this thread or
+    /// any other, and no second call to `get_unchecked`. Holding the lock
+    /// while using the reference is one way to guarantee it, external
+    /// synchronization such as a join that proves every other thread is done
+    /// is another. The lock itself is left untouched, so `is_locked` and
+    /// poisoning don't reflect writes made through it.
+    ///
+    /// ```
+    /// use mutex::Mutex;
+    ///
+    /// let m = Mutex::new(1);
+    /// // Single threaded and no guard alive, nothing else can see the value
+    /// unsafe { *m.get_unchecked() += 1 };
+    /// assert_eq!(*m.lock().unwrap(), 2);
+    /// ```
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_unchecked(&self) -> &mut T {
+        &mut *self.v.get()
+    }
+
+    /// Projects a pinned mutex to its pinned data, without locking since the
+    /// `Pin<&mut Self>` is exclusive.
+    ///
+    /// If `T: !Unpin` and you need safe pinned access, store a
+    /// `Mutex<Pin<Box<T>>>` and use `guard.as_mut()` instead.
+    ///
+    /// # Safety
+    ///
+    /// A pinned mutex still derefs to `&Mutex<T>`, and [`lock`](Self::lock),
+    /// [`replace`](Self::replace), [`take`](Self::take) and friends all hand
+    /// out ways to move `T` through that shared reference. After calling this
+    /// the caller must never move the data again until it is dropped in
+    /// place. That rules out `mem::swap` / `mem::replace` through a guard,
+    /// `replace` / `take` / `update` on the mutex, and anything else that
+    /// moves it out. Reading and mutating in place is fine.
+    ///
+    /// ```
+    /// use mutex::Mutex;
+    /// use std::pin::pin;
+    ///
+    /// let m = pin!(Mutex::new(5));
+    /// let v = unsafe { m.get_pin_mut() };
+    /// *v.get_mut() += 1;
+    /// ```
+    pub unsafe fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.map_unchecked_mut(|m| m.v.get_mut())
+    }
+}
+
+/// Locks both mutexes, always in the same global order no matter which one is
+/// passed first. Two threads calling `lock2(&a, &b)` and `lock2(&b, &a)` can't
+/// deadlock.
+///
+/// The order is the mutexes' addresses, or their lock ids with the
+/// `deadlock-detection` feature so the order check agrees. Poisoning is
+/// ignored like in [`Mutex::with_lock`]. Panics if `a` and `b` are the same
+/// mutex.
+#[track_caller]
+pub fn lock2<'a, A: ?Sized, B: ?Sized>(
+    a: &'a Mutex<A>,
+    b: &'a Mutex<B>,
+) -> (MutexGuard<'a, A>, MutexGuard<'a, B>) {
+    let (ka, kb) = (a.order_key(), b.order_key());
+    assert!(ka != kb, "lock2 called with the same mutex twice");
+    if ka < kb {
+        let ga = a.lock().unwrap_or_else(PoisonError::into_inner);
+        (ga, b.lock().unwrap_or_else(PoisonError::into_inner))
+    } else {
+        let gb = b.lock().unwrap_or_else(PoisonError::into_inner);
+        (a.lock().unwrap_or_else(PoisonError::into_inner), gb)
+    }
+}
+
+/// Locks both mutexes without relying on an order. Takes one, tries the other
+/// and on failure lets go of both, backs off and starts over from the other
+/// one, so nothing is ever held while waiting.
+///
+/// Slower than [`lock2`] under contention but works for any set of locks, not
+/// just ones that can be compared. Panics if `a` and `b` are the same mutex.
+#[track_caller]
+pub fn lock2_retry<'a, A: ?Sized, B: ?Sized>(
+    a: &'a Mutex<A>,
+    b: &'a Mutex<B>,
+) -> (MutexGuard<'a, A>, MutexGuard<'a, B>) {
+    assert!(
+        a.order_key() != b.order_key(),
+        "lock2_retry called with the same mutex twice"
+    );
+    let mut backoff = DefaultBackoff::default();
+    let mut attempts = 0;
+    loop {
+        let ga = a.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(gb) = b.try_lock() {
+            return (ga, gb);
+        }
+        drop(ga);
+        back_off(&mut backoff, &mut attempts);
+
+        // Wait on the one that was busy this time round
+        let gb = b.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(ga) = a.try_lock() {
+            return (ga, gb);
+        }
+        drop(gb);
+        back_off(&mut backoff, &mut attempts);
+    }
+}
+
+fn back_off(backoff: &mut DefaultBackoff, attempts: &mut usize) {
+    *attempts += 1;
+    if *attempts > YIELD_THRESHOLD {
+        sync::yield_now();
+    } else {
+        backoff.snooze();
+    }
+}
+
+/// A lock that guards nothing itself, for serializing access to state that
+/// lives elsewhere.
+///
+/// ```
+/// # #[cfg(not(feature = "single-thread"))] {
+/// use mutex::RawLock;
+///
+/// static LOG: RawLock = RawLock::new(());
+///
+/// let _guard = LOG.guard();
+/// // Only one thread at a time gets here
+/// # }
+/// ```
+pub type RawLock = Mutex<()>;
+
+/// Guard returned by [`Mutex::guard`].
+pub type RawGuard<'a, B = DefaultBackoff> = MutexGuard<'a, (), B>;
+
+impl<B: Backoff> Mutex<(), B> {
+    /// Spins until the lock is acquired. There is no data to unwrap, so a
+    /// poisoned lock is entered anyway like in [`Mutex::with_lock`].
+    #[track_caller]
+    pub fn guard(&self) -> RawGuard<'_, B> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `f` while holding the lock.
+    #[track_caller]
+    pub fn critical<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.with_lock(|()| f())
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Same as [`Mutex::new`], like `std::sync::Mutex`.
+///
+/// ```
+/// use mutex::Mutex;
+///
+/// let m: Mutex<u32> = 5.into();
+/// assert_eq!(m.into_inner(), 5);
+/// ```
+impl<T> From<T> for Mutex<T> {
+    fn from(t: T) -> Self {
+        Self::new(t)
+    }
+}
+
+/// Locks just long enough to clone the value into a new, unlocked
+/// `Mutex::new`. The two are independent afterwards, and the clone starts
+/// unpoisoned with the default settings whatever the original was built with.
+impl<T: Clone> Clone for Mutex<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        Self::new(self.with_lock(|v| v.clone()))
+    }
+}
+
+/// Takes both locks, through [`lock2`] so two threads comparing `a == b` and
+/// `b == a` can't deadlock, and compares the values. Poisoning is ignored.
+/// Comparing a mutex with itself locks it once.
+impl<T: ?Sized + PartialEq> PartialEq for Mutex<T> {
+    #[track_caller]
+    fn eq(&self, other: &Self) -> bool {
+        if core::ptr::eq(self, other) {
+            // Still compared, a NaN-like value isn't equal to itself
+            return self.with_lock(|v| T::eq(v, v));
+        }
+        let (a, b) = lock2(self, other);
+        *a == *b
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Mutex<T> {}
+
+/// Locks and hashes the value. Together with `Eq` this lets mutexes go into a
+/// `HashSet`, as long as nothing changes their values while they are in it.
+impl<T: ?Sized + Hash> Hash for Mutex<T> {
+    #[track_caller]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.with_lock(|v| v.hash(state));
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, B: Backoff> fmt::Debug for Mutex<T, B> {
+    // Never blocks, a held lock is printed as a placeholder. Goes through peek
+    // like Display, so it isn't counted as a lock
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct LockedPlaceholder;
+        impl fmt::Debug for LockedPlaceholder {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("<locked>")
+            }
+        }
+
+        let mut d = f.debug_struct("Mutex");
+        if let Some(name) = self.name() {
+            d.field("name", &name);
+        }
+        if self.peek(|v| d.field("data", &v)).is_none() {
+            d.field("data", &LockedPlaceholder);
+        }
+        d.field("poisoned", &self.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Prints the value, or `<locked>` if another guard holds it. Never blocks,
+/// like `Debug`, and doesn't count as a lock: stats, metrics, the hold timer
+/// and [`Mutex::generation`] don't see it.
+///
+/// ```
+/// let m = mutex::Mutex::new(String::from("ready"));
+/// assert_eq!(m.to_string(), "ready");
+/// let _guard = m.lock().unwrap();
+/// assert_eq!(m.to_string(), "<locked>");
+/// ```
+impl<T: ?Sized + fmt::Display, B: Backoff> fmt::Display for Mutex<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.peek(|v| fmt::Display::fmt(v, f))
+            .unwrap_or_else(|| f.write_str("<locked>"))
+    }
+}
+
+// No Backoff bound, the guards' Drop impls can't have one
+impl<T: ?Sized, B> Mutex<T, B> {
+    /// The name given to [`Mutex::new_named`], if it was kept.
+    pub fn name(&self) -> Option<&'static str> {
+        #[cfg(any(feature = "debug-locks", feature = "tracing", feature = "metrics"))]
+        return self.name;
+        #[cfg(not(any(feature = "debug-locks", feature = "tracing", feature = "metrics")))]
+        None
+    }
+
+    // Shared by the guards' drops. Poison only for panics that started while
+    // the guard was held
+    fn unlock(&self, was_panicking: bool) {
+        #[cfg(feature = "deadlock-detection")]
+        self.id.released();
+        #[cfg(feature = "debug-locks")]
+        self.held_at.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(all(feature = "debug-locks", feature = "std"))]
+        self.holder.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(feature = "std")]
+        end_hold(&self.hold_timer, self.name());
+        unlock(
+            &self.locked,
+            &self.poisoned,
+            &self.generation,
+            self.release,
+            was_panicking,
+        );
+        #[cfg(feature = "std")]
+        wake_parked(self.parked());
+        #[cfg(feature = "async")]
+        wake_async(&self.wakers);
+    }
+
+    // The wait queue of a mutex that parks, for guards that release it
+    #[cfg(feature = "std")]
+    fn parked(&self) -> Option<&WaitQueue> {
+        (self.park_after.is_some() || self.spin_limited).then_some(&self.parked)
+    }
+
+    // Debug and Display's look at the value. A strong CAS so a free lock is
+    // never reported as held, and a bare release without a guard's
+    // bookkeeping: printing a mutex doesn't show up in stats, metrics, the
+    // hold timer or generation. Nothing is written, so a panic in `f` doesn't
+    // poison
+    fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        struct Release<'a, T: ?Sized, B>(&'a Mutex<T, B>);
+
+        impl<T: ?Sized, B> Drop for Release<'_, T, B> {
+            fn drop(&mut self) {
+                self.0.locked.store(UNLOCKED, self.0.release);
+                // Someone may have started waiting in the meantime
+                #[cfg(feature = "std")]
+                wake_parked(self.0.parked());
+                #[cfg(feature = "async")]
+                wake_async(&self.0.wakers);
+            }
+        }
+
+        self.locked
+            .compare_exchange(UNLOCKED, LOCKED, self.acquire, Ordering::Relaxed)
+            .ok()?;
+        let _release = Release(self);
+        Some(f(unsafe { &*self.v.get() }))
+    }
+}
+
+// Stops the clock of a slow-hold threshold, still holding the lock it times
+#[cfg(feature = "std")]
+fn end_hold(timer: &HoldTimer, name: Option<&'static str>) {
+    let slow = unsafe { timer.stop() };
+    #[cfg(feature = "tracing")]
+    if let Some(held) = slow {
+        tracing::warn!(name, ?held, "Mutex held longer than its threshold");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = (slow, name);
+}
+
+// Free standing so MappedMutexGuard can release without knowing `T`
+fn unlock(
+    locked: &AtomicBool,
+    poisoned: &AtomicBool,
+    generation: &sync::AtomicUsize,
+    release: Ordering,
+    was_panicking: bool,
+) {
+    if !was_panicking && sync::panicking() {
+        poisoned.store(true, Ordering::Relaxed);
+    }
+    self::release(locked, generation, release);
+}
+
+// Debug builds swap instead of store so releasing a lock nobody holds panics
+// instead of silently going through
+fn release(locked: &AtomicBool, generation: &sync::AtomicUsize, order: Ordering) {
+    // Only the holder ever writes it, no read-modify-write needed. Release so
+    // a reader that sees the new count also sees the critical section's writes
+    let next = generation.load(Ordering::Relaxed).wrapping_add(1);
+    generation.store(next, Ordering::Release);
+    #[cfg(debug_assertions)]
+    assert!(
+        locked.swap(UNLOCKED, order) == LOCKED,
+        "unlocking a mutex that isn't locked"
+    );
+    #[cfg(not(debug_assertions))]
+    locked.store(UNLOCKED, order);
+}
+
+/// RAII guard returned by [`Mutex::lock`], the lock is released when it is
+/// dropped.
+///
+/// Dropping it straight away is almost always a bug, so ignoring a guard or a
+/// `try_lock` result warns.
+///
+/// The guard has to be dropped on the thread that locked, so it can't be moved
+/// to another one. It is `Sync` when `T` is, sharing `&guard` is fine.
+#[must_use = "if unused the lock is released immediately"]
+pub struct MutexGuard<'a, T: ?Sized, B = DefaultBackoff> {
+    mutex: &'a Mutex<T, B>,
+    // Poison only for panics that started while we held the lock, not for a
+    // lock taken during unwinding
+    panicking: bool,
+    // Raw pointers are !Send, the guard must be released on the thread that
+    // took the lock
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized, B: Sync> Sync for MutexGuard<'_, T, B> where T: Sync {}
+
+impl<'a, T: ?Sized, B: Backoff> MutexGuard<'a, T, B> {
+    #[track_caller]
+    fn new(mutex: &'a Mutex<T, B>) -> Self {
+        #[cfg(feature = "debug-locks")]
+        mutex
+            .held_at
+            .store(Location::caller() as *const _ as *mut _, Ordering::Relaxed);
+        #[cfg(all(feature = "debug-locks", feature = "std"))]
+        mutex.holder.store(
+            sync::current_thread_id() as *const _ as *mut _,
+            Ordering::Relaxed,
+        );
+        #[cfg(feature = "deadlock-detection")]
+        mutex.id.acquired();
+        #[cfg(all(debug_assertions, feature = "std"))]
+        mutex.owner.store(sync::thread_token(), Ordering::Relaxed);
+        // We hold the lock now
+        #[cfg(feature = "std")]
+        unsafe {
+            mutex.hold_timer.start()
+        };
+        Self {
+            mutex,
+            panicking: sync::panicking(),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Narrows the guard to a part of the protected value, the lock stays held
+    /// until the returned guard is dropped.
+    ///
+    /// An associated function so it doesn't shadow a `map` method on `T`.
+    ///
+    /// ```
+    /// use mutex::{Mutex, MutexGuard};
+    ///
+    /// let m = Mutex::new((1, String::new()));
+    /// let mut name = MutexGuard::map(m.lock().unwrap(), |v| &mut v.1);
+    /// name.push_str("spin");
+    /// drop(name);
+    /// assert_eq!(m.with_lock(|v| v.1.clone()), "spin");
+    /// ```
+    pub fn map<U: ?Sized>(this: Self, f: impl FnOnce(&mut T) -> &mut U) -> MappedMutexGuard<'a, U> {
+        // If `f` panics `this` is still alive and unlocks (and poisons) as usual
+        let value = f(unsafe { &mut *this.mutex.v.get() });
+        Self::into_mapped(this, value)
+    }
+
+    /// Like [`MutexGuard::map`] for projections that can fail. If `f` returns
+    /// `None` the original guard is handed back, still locked.
+    pub fn filter_map<U: ?Sized>(
+        this: Self,
+        f: impl FnOnce(&mut T) -> Option<&mut U>,
+    ) -> Result<MappedMutexGuard<'a, U>, Self> {
+        match f(unsafe { &mut *this.mutex.v.get() }) {
+            Some(value) => Ok(Self::into_mapped(this, value)),
+            None => Err(this),
+        }
+    }
+
+    /// Releases the lock now, the same as dropping the guard but explicit at
+    /// the call site. Consuming the guard rules out touching the data after.
+    ///
+    /// ```
+    /// use mutex::{Mutex, MutexGuard};
+    ///
+    /// let m = Mutex::new(0);
+    /// let mut guard = m.lock().unwrap();
+    /// *guard += 1;
+    /// MutexGuard::unlock(guard);
+    /// assert!(!m.is_locked());
+    /// ```
+    pub fn unlock(this: Self) {
+        drop(this);
+    }
+
+    /// Pinned access to the locked data. Callable on any guard, nothing here
+    /// checks that the mutex itself is pinned.
+    ///
+    /// If `T: !Unpin` and you need safe pinned access, store a
+    /// `Mutex<Pin<Box<T>>>` and use `guard.as_mut()` instead.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee the mutex is pinned: it is never moved again
+    /// until it is dropped in place, since the data lives inside it. The data
+    /// itself must not be moved out either once it has been handed out
+    /// pinned, through this guard, a later one, or the mutex, see
+    /// [`Mutex::get_pin_mut`].
+    ///
+    /// ```
+    /// use mutex::{Mutex, MutexGuard};
+    /// use std::pin::pin;
+    ///
+    /// let m = pin!(Mutex::new(5));
+    /// let mut guard = m.as_ref().get_ref().lock().unwrap();
+    /// let v = unsafe { MutexGuard::as_pin_mut(&mut guard) };
+    /// *v.get_mut() += 1;
+    /// ```
+    pub unsafe fn as_pin_mut(this: &mut Self) -> Pin<&mut T> {
+        Pin::new_unchecked(&mut **this)
+    }
+
+    /// Forgets the guard and returns a reference to the data that lives as
+    /// long as the mutex borrow. The lock stays held, useful to keep it across
+    /// an FFI boundary.
+    ///
+    /// Nothing will release it on its own. Unless the caller later calls
+    /// [`Mutex::force_unlock`], every other thread waiting for the lock spins
+    /// forever.
+    ///
+    /// ```
+    /// use mutex::{Mutex, MutexGuard};
+    ///
+    /// let m = Mutex::new(0);
+    /// let v = MutexGuard::leak(m.lock().unwrap());
+    /// *v += 1;
+    /// assert!(m.is_locked());
+    /// // `v` isn't used past this point
+    /// unsafe { m.force_unlock() };
+    /// assert_eq!(*m.lock().unwrap(), 1);
+    /// ```
+    pub fn leak(this: Self) -> &'a mut T {
+        let value = this.mutex.v.get();
+        core::mem::forget(this);
+        unsafe { &mut *value }
+    }
+
+    /// Releases the lock while `f` runs and takes it again before returning,
+    /// so other threads can make progress in the middle of a long critical
+    /// section.
+    ///
+    /// The lock is re-acquired even if `f` panics, the guard then poisons the
+    /// mutex when it is dropped during unwinding.
+    ///
+    /// ```
+    /// use mutex::{Mutex, MutexGuard};
+    ///
+    /// let m = Mutex::new(0);
+    /// let mut guard = m.lock().unwrap();
+    /// MutexGuard::unlocked(&mut guard, || m.with_lock(|v| *v += 1));
+    /// assert_eq!(*guard, 1);
+    /// ```
+    pub fn unlocked<R>(this: &mut Self, f: impl FnOnce() -> R) -> R {
+        struct Relock<'b, T: ?Sized, B: Backoff>(&'b Mutex<T, B>);
+
+        impl<T: ?Sized, B: Backoff> Drop for Relock<'_, T, B> {
+            fn drop(&mut self) {
+                // `this` goes on as the guard of the new acquisition
+                core::mem::forget(self.0.lock());
+            }
+        }
+
+        this.mutex.unlock(this.panicking);
+        let _relock = Relock(this.mutex);
+        f()
+    }
+
+    // Only a raw pointer is kept, `value` must not outlive the lock
+    fn into_mapped<U: ?Sized>(this: Self, value: *mut U) -> MappedMutexGuard<'a, U> {
+        let mapped = MappedMutexGuard {
+            locked: &this.mutex.locked,
+            poisoned: &this.mutex.poisoned,
+            generation: &this.mutex.generation,
+            #[cfg(feature = "debug-locks")]
+            held_at: &this.mutex.held_at,
+            #[cfg(all(feature = "debug-locks", feature = "std"))]
+            holder: &this.mutex.holder,
+            #[cfg(feature = "deadlock-detection")]
+            id: &this.mutex.id,
+            #[cfg(feature = "std")]
+            hold_timer: &this.mutex.hold_timer,
+            #[cfg(feature = "std")]
+            name: this.mutex.name(),
+            #[cfg(feature = "std")]
+            parked: this.mutex.parked(),
+            #[cfg(feature = "async")]
+            wakers: &this.mutex.wakers,
+            release: this.mutex.release,
+            panicking: this.panicking,
+            value,
+            _marker: PhantomData,
+        };
+        core::mem::forget(this);
+        mapped
+    }
+
+    // Used by Condvar to relock after waiting
+    #[cfg(feature = "std")]
+    pub(crate) fn mutex(&self) -> &'a Mutex<T, B> {
+        self.mutex
+    }
+}
+
+impl<T: ?Sized, B> Deref for MutexGuard<'_, T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T: ?Sized, B> DerefMut for MutexGuard<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+// Forwards to T like the impls for &T and &mut T do, so a guard of a String
+// passes for AsRef<str>
+impl<T: ?Sized + AsRef<U>, U: ?Sized, B> AsRef<U> for MutexGuard<'_, T, B> {
+    fn as_ref(&self) -> &U {
+        (**self).as_ref()
+    }
+}
+
+impl<T: ?Sized + AsMut<U>, U: ?Sized, B> AsMut<U> for MutexGuard<'_, T, B> {
+    fn as_mut(&mut self) -> &mut U {
+        (**self).as_mut()
+    }
+}
+
+impl<T: ?Sized, B> Drop for MutexGuard<'_, T, B> {
+    fn drop(&mut self) {
+        self.mutex.unlock(self.panicking);
+    }
+}
+
+// Pairs with the fence in Mutex::park
+#[cfg(feature = "std")]
+fn wake_parked(parked: Option<&WaitQueue>) {
+    if let Some(parked) = parked {
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        parked.wake_one();
+    }
+}
+
+// Pairs with the fence in AsyncLock::poll. Either the waiter's retry sees the
+// lock released or we see its waker, it can't miss both
+#[cfg(feature = "async")]
+fn wake_async(wakers: &WakerQueue) {
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    wakers.wake_one();
+}
+
+#[cfg(feature = "async")]
+struct AsyncLock<'a, T: ?Sized, B> {
+    mutex: &'a Mutex<T, B>,
+    // Set while our waker is queued
+    waker: Option<core::task::Waker>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, T: ?Sized, B: Backoff> Future for AsyncLock<'a, T, B> {
+    type Output = AsyncMutexGuard<'a, T, B>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        if let Some(guard) = mutex.try_lock() {
+            return Poll::Ready(AsyncMutexGuard::new(guard, &mut self.waker));
+        }
+        mutex.wakers.register(cx.waker());
+        self.waker = Some(cx.waker().clone());
+        // The holder may have unlocked before our waker was queued
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        match mutex.try_lock() {
+            Some(guard) => Poll::Ready(AsyncMutexGuard::new(guard, &mut self.waker)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized, B> Drop for AsyncLock<'_, T, B> {
+    fn drop(&mut self) {
+        // Cancelled after an unlock picked us, hand the wake up on or the next
+        // waiter sleeps until some later unlock
+        if let Some(waker) = self.waker.take() {
+            if !self.mutex.wakers.deregister(&waker) {
+                self.mutex.wakers.wake_one();
+            }
+        }
+    }
+}
+
+/// Guard returned by [`Mutex::async_lock`], the lock is released when it is
+/// dropped.
+///
+/// It is `Send` when `T` is so it can be held across an `.await` in a task
+/// that moves between threads.
+#[cfg(feature = "async")]
+#[must_use = "if unused the lock is released immediately"]
+pub struct AsyncMutexGuard<'a, T: ?Sized, B = DefaultBackoff> {
+    mutex: &'a Mutex<T, B>,
+    panicking: bool,
+}
+
+#[cfg(feature = "async")]
+unsafe impl<T: ?Sized, B: Sync> Send for AsyncMutexGuard<'_, T, B> where T: Send {}
+#[cfg(feature = "async")]
+unsafe impl<T: ?Sized, B: Sync> Sync for AsyncMutexGuard<'_, T, B> where T: Sync {}
+
+#[cfg(feature = "async")]
+impl<'a, T: ?Sized, B> AsyncMutexGuard<'a, T, B> {
+    fn new(guard: MutexGuard<'a, T, B>, waker: &mut Option<core::task::Waker>) -> Self {
+        let mutex = guard.mutex;
+        let panicking = guard.panicking;
+        core::mem::forget(guard);
+        // Whoever woke us already popped the waker, otherwise drop it now
+        if let Some(waker) = waker.take() {
+            mutex.wakers.deregister(&waker);
+        }
+        Self { mutex, panicking }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized, B> Deref for AsyncMutexGuard<'_, T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized, B> DerefMut for AsyncMutexGuard<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: ?Sized, B> Drop for AsyncMutexGuard<'_, T, B> {
+    fn drop(&mut self) {
+        self.mutex.unlock(self.panicking);
+    }
+}
+
+/// Guard returned by [`MutexGuard::map`] pointing at part of the protected
+/// value. Holds the lock of the original mutex until it is dropped.
+#[must_use = "if unused the lock is released immediately"]
+pub struct MappedMutexGuard<'a, U: ?Sized> {
+    locked: &'a AtomicBool,
+    poisoned: &'a AtomicBool,
+    generation: &'a sync::AtomicUsize,
+    #[cfg(feature = "debug-locks")]
+    held_at: &'a AtomicPtr<Location<'static>>,
+    #[cfg(all(feature = "debug-locks", feature = "std"))]
+    holder: &'a AtomicPtr<ThreadId>,
+    #[cfg(feature = "deadlock-detection")]
+    id: &'a LockId,
+    #[cfg(feature = "std")]
+    hold_timer: &'a HoldTimer,
+    #[cfg(feature = "std")]
+    name: Option<&'static str>,
+    #[cfg(feature = "std")]
+    parked: Option<&'a WaitQueue>,
+    #[cfg(feature = "async")]
+    wakers: &'a WakerQueue,
+    release: Ordering,
+    panicking: bool,
+    // The raw pointer also keeps the guard !Send like MutexGuard
+    value: *mut U,
+    _marker: PhantomData<&'a mut U>,
+}
+
+unsafe impl<U: ?Sized> Sync for MappedMutexGuard<'_, U> where U: Sync {}
+
+impl<U: ?Sized> Deref for MappedMutexGuard<'_, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { &*self.value }
+    }
+}
+
+impl<U: ?Sized> DerefMut for MappedMutexGuard<'_, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<U: ?Sized> Drop for MappedMutexGuard<'_, U> {
+    fn drop(&mut self) {
+        #[cfg(feature = "debug-locks")]
+        self.held_at.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(all(feature = "debug-locks", feature = "std"))]
+        self.holder.store(ptr::null_mut(), Ordering::Relaxed);
+        #[cfg(feature = "std")]
+        end_hold(self.hold_timer, self.name);
+        #[cfg(feature = "deadlock-detection")]
+        self.id.released();
+        unlock(
+            self.locked,
+            self.poisoned,
+            self.generation,
+            self.release,
+            self.panicking,
+        );
+        #[cfg(feature = "std")]
+        wake_parked(self.parked);
+        #[cfg(feature = "async")]
+        wake_async(self.wakers);
+    }
+}
+
+/// Owned guard returned by [`Mutex::lock_arc`], the lock is released when it
+/// is dropped.
+///
+/// Unlike [`MutexGuard`] this one is `Send` when `T` is. A spin lock is just a
+/// flag with no owning thread, so releasing it from another thread is fine.
+/// That makes it the guard to keep in a struct field and release later,
+/// wherever the struct ends up.
+///
+/// ```
+/// # #[cfg(not(feature = "single-thread"))] {
+/// use mutex::{ArcMutexGuard, Mutex};
+///
+/// struct Reservation {
+///     seats: ArcMutexGuard<u32>,
+/// }
+///
+/// let m = Mutex::new_arc(10);
+/// let r = Reservation { seats: m.lock_arc().unwrap() };
+/// std::thread::spawn(move || {
+///     let mut r = r;
+///     *r.seats -= 2;
+///     // Dropping `r` releases the lock on this thread
+/// })
+/// .join()
+/// .unwrap();
+/// assert_eq!(m.with_lock(|v| *v), 8);
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[must_use = "if unused the lock is released immediately"]
+pub struct ArcMutexGuard<T: ?Sized, B = DefaultBackoff> {
+    mutex: Arc<Mutex<T, B>>,
+    panicking: bool,
+}
+
+// The Arc alone would make the guard Sync for any `T: Send`, but sharing the
+// guard shares `&T`
+#[cfg(feature = "std")]
+unsafe impl<T: ?Sized, B: Send + Sync> Sync for ArcMutexGuard<T, B> where T: Sync {}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized, B> ArcMutexGuard<T, B> {
+    /// The mutex this guard is holding.
+    pub fn mutex(&self) -> &Arc<Mutex<T, B>> {
+        &self.mutex
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized, B> Deref for ArcMutexGuard<T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized, B> DerefMut for ArcMutexGuard<T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized, B> Drop for ArcMutexGuard<T, B> {
+    fn drop(&mut self) {
+        self.mutex.unlock(self.panicking);
+    }
+}
+
+// The way to think about Release and Aqcuire is that acquire-release pair establishes a happens
+// before relationship between the thread that previously released the lock and
+// the next thread that takes the lock
+
+// Before-After relationship states that what happens before the thing that triggered stored also
+// happend before what happens after load, think of this as a bar like this
+
+// a + 50  ---- 2 Thread B wants to aquire the lock and add 50.
+//
+// ==========================LOAD `a` (acquire) ================================= Thread A
+//...
+//...
+//... other instructions
+//... a + 10 ----  1
+//...
+//... end
+//===========================STORE `a` (release) ================================
+
+// Since Thread A mutex allows releases only after execution, the next thread that will aquire the lock will see the
+// updated value
+
+// TODO: Learn MESI protocol
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "single-thread"))]
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    #[cfg(not(feature = "single-thread"))]
+    use std::thread::spawn;
+    use std::time::Duration;
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn too_relaxed() {
+        fn mutex_test() {
+            let l: &'static _ = Box::leak(Box::new(Mutex::new(0)));
+            let handles: Vec<_> = (0..100)
+                .map(|_| {
+                    spawn(move || {
+                        for _ in 0..1000 {
+                            l.with_lock(|v| {
+                                *v += 1;
+                            })
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(l.with_lock(|v| *v), 100 * 1000);
+        }
+        mutex_test();
+
+        let x: &'static _ = Box::leak(Box::new(AtomicUsize::new(0)));
+        let y: &'static _ = Box::leak(Box::new(AtomicUsize::new(0)));
+        let t1 = spawn(move || {
+            let r1 = y.load(Ordering::Relaxed);
+            x.store(r1, Ordering::Relaxed);
+            r1
+        });
+
+        let t2 = spawn(move || {
+            let r2 = x.load(Ordering::Relaxed);
+            y.store(42, Ordering::Relaxed);
+            r2
+        });
+
+        let r1 = t1.join().unwrap();
+        let r2 = t2.join().unwrap();
+        let _ = (r1, r2);
+        // r1 == r2 == 42 :)
+        // Why?
+
+        // Modification Order
+        // MO(x): 0 42
+        // MO(y): 0 42
+
+        // With Order Relaxed, when you load a value you can read any value
+        // written by any thread, there is no restriction of when last a write happend relative to you
+    }
+
+    // The same freedom applied to a lock: with Relaxed on the flag, nothing
+    // would order the previous holder's plain writes before our reads, and a
+    // reader could see half of one write and half of another (tests/loom.rs
+    // shows loom catching exactly that). Acquire on lock and Release on unlock
+    // forbid it, every word of the array must always come from the same write
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn guarded_writes_are_never_torn() {
+        let l = Mutex::new([0u64; 8]);
+        std::thread::scope(|s| {
+            for t in 0..4u64 {
+                let l = &l;
+                s.spawn(move || {
+                    for i in 0..10_000 {
+                        l.with_lock(|words| {
+                            let first = words[0];
+                            assert!(words.iter().all(|&w| w == first), "torn: {words:?}");
+                            *words = [t << 32 | i; 8];
+                        });
+                    }
+                });
+            }
+        });
+        let words = l.into_inner();
+        assert!(words.iter().all(|&w| w == words[0]));
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn guard_holds_lock_across_statements() {
+        let l: &'static _ = Box::leak(Box::new(Mutex::new(Vec::new())));
+        let handles: Vec<_> = (0..10)
+            .map(|i| {
+                spawn(move || {
+                    let mut guard = l.lock().unwrap();
+                    guard.push(i);
+                    guard.push(i);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let guard = l.lock().unwrap();
+        assert_eq!(guard.len(), 20);
+        // Each thread pushed twice while holding the guard so pairs never interleave
+        for pair in guard.chunks(2) {
+            assert_eq!(pair[0], pair[1]);
+        }
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn try_lock_fails_when_contended() {
+        let l: &'static _ = Box::leak(Box::new(Mutex::new(0)));
+        let done: &'static _ = Box::leak(Box::new(AtomicBool::new(false)));
+        let holder = spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                l.with_lock(|v| {
+                    *v += 1;
+                    std::thread::sleep(Duration::from_millis(1));
+                });
+            }
+        });
+        let mut saw_none = false;
+        while !saw_none {
+            saw_none = l.try_with_lock(|v| *v).is_none();
+        }
+        done.store(true, Ordering::Relaxed);
+        holder.join().unwrap();
+        assert!(l.with_lock(|v| *v) > 0);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn custom_backoff_still_counts_correctly() {
+        for (initial, max) in [(0, 0), (1, 1), (1, 4096)] {
+            let l = Mutex::with_backoff(0, initial, max);
+            std::thread::scope(|s| {
+                for _ in 0..16 {
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            l.with_lock(|v| *v += 1);
+                        }
+                    });
+                }
+            });
+            assert_eq!(l.with_lock(|v| *v), 16 * 1000);
+        }
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn yielding_waiters_reach_correct_count() {
+        let l = Mutex::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..100 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| {
+                            // Keep the lock long enough for waiters to pass YIELD_THRESHOLD
+                            for _ in 0..YIELD_THRESHOLD {
+                                std::hint::spin_loop();
+                            }
+                            *v += 1;
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(l.with_lock(|v| *v), 100 * 1000);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn with_lock_releases_when_f_panics() {
+        let l = Mutex::new(0);
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            l.with_lock(|_| panic!("boom"));
+        }));
+        assert!(r.is_err());
+        assert!(!l.is_locked());
+        std::thread::scope(|s| {
+            assert!(s.spawn(|| l.try_lock().is_some()).join().unwrap());
+        });
+    }
+
+    #[test]
+    fn panic_while_locked_poisons() {
+        let l = Mutex::new(0);
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            l.with_lock(|v| {
+                *v += 1;
+                panic!("boom");
+            })
+        }));
+        assert!(r.is_err());
+        assert!(l.is_poisoned());
+
+        let Err(err) = l.lock() else {
+            panic!("lock should be poisoned");
+        };
+        assert_eq!(**err.get_ref(), 1);
+        let mut guard = err.into_inner();
+        *guard += 1;
+        drop(guard);
+        // Recovering the guard doesn't clear the flag
+        assert!(l.is_poisoned());
+
+        l.clear_poison();
+        assert!(!l.is_poisoned());
+        assert_eq!(*l.lock().unwrap(), 2);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn into_inner_and_get_mut() {
+        let mut l = Mutex::new(Vec::<u32>::new());
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let l = &l;
+                s.spawn(move || l.with_lock(|v| v.push(i)));
+            }
+        });
+        l.get_mut().sort();
+        assert_eq!(l.get_mut(), &[0, 1, 2, 3]);
+        l.get_mut().push(4);
+        assert_eq!(l.into_inner(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn static_mutex() {
+        static COUNTER: Mutex<u32> = Mutex::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        COUNTER.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(COUNTER.with_lock(|v| *v), 800);
+    }
+
+    #[test]
+    fn default_mutex() {
+        use std::collections::HashMap;
+
+        let l: Mutex<HashMap<String, u32>> = Default::default();
+        l.with_lock(|m| m.insert("a".to_string(), 1));
+        assert_eq!(l.with_lock(|m| m.get("a").copied()), Some(1));
+        assert!(Mutex::<Vec<u8>>::default().into_inner().is_empty());
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn debug_does_not_block_on_held_lock() {
+        let l = Mutex::new(7);
+        assert_eq!(format!("{l:?}"), "Mutex { data: 7, poisoned: false, .. }");
+
+        let (held_tx, held_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        std::thread::scope(|s| {
+            let l = &l;
+            s.spawn(move || {
+                let _guard = l.lock().unwrap();
+                held_tx.send(()).unwrap();
+                done_rx.recv().unwrap();
+            });
+            held_rx.recv().unwrap();
+            assert_eq!(
+                format!("{l:?}"),
+                "Mutex { data: <locked>, poisoned: false, .. }"
+            );
+            done_tx.send(()).unwrap();
+        });
+    }
+
+    #[test]
+    fn is_locked_tracks_guard() {
+        let l = Mutex::new(());
+        assert!(!l.is_locked());
+        let guard = l.lock().unwrap();
+        assert!(l.is_locked());
+        drop(guard);
+        assert!(!l.is_locked());
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn seq_cst_ordering_counts_correctly() {
+        let l = Mutex::new_with_ordering(0, Ordering::SeqCst, Ordering::SeqCst);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.with_lock(|v| *v), 8 * 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "release ordering")]
+    fn acquire_release_store_is_rejected() {
+        let _ = Mutex::new_with_ordering(0, Ordering::Acquire, Ordering::Acquire);
+    }
+
+    #[cfg(all(feature = "stats", not(feature = "single-thread")))]
+    #[test]
+    fn stats_count_contention() {
+        let l = Mutex::new(0);
+        assert_eq!(l.stats(), MutexStats::default());
+
+        let guard = l.lock().unwrap();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| l.with_lock(|v| *v += 1));
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            waiter.join().unwrap();
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+
+        let stats = l.stats();
+        assert_eq!(stats.acquisitions, 2 + 8 * 1000);
+        // The waiter failed at least once against our held guard
+        assert!(stats.total_spins > 0);
+        l.reset_stats();
+        assert_eq!(l.stats(), MutexStats::default());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn uncontended_waits_land_in_the_first_bucket() {
+        let l = Mutex::new(0);
+        for _ in 0..100 {
+            l.with_lock(|v| *v += 1);
+        }
+        assert!(l.try_lock().is_some());
+        assert_eq!(l.wait_histogram(), [101, 0, 0, 0, 0]);
+        l.reset_stats();
+        assert_eq!(l.wait_histogram(), [0; 5]);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn wait_buckets_split_at_powers_of_ten() {
+        let stats = crate::stats::Stats::new();
+        for spins in [0, 1, 9, 10, 99, 100, 999, 1000, usize::MAX] {
+            stats.record(spins);
+        }
+        assert_eq!(stats.waits(), [1, 2, 2, 2, 2]);
+    }
+
+    #[cfg(all(feature = "stats", not(feature = "single-thread")))]
+    #[test]
+    fn snapshot_stats_never_tears() {
+        let l = Mutex::new(0);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    l.reset_stats();
+                    std::thread::yield_now();
+                }
+            });
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let now = l.snapshot_stats();
+                    // Spins only ever get recorded together with an acquisition,
+                    // a torn read across a reset could see zero of one but not
+                    // the other
+                    assert!(now.acquisitions > 0 || now.total_spins == 0, "{now:?}");
+                }
+            });
+            while l.with_lock(|v| *v) < 4 * 2000 {
+                std::thread::yield_now();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn contention_hook_only_fires_when_waiting() {
+        let l = Mutex::new(0);
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = fired.clone();
+        l.on_contention(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        for _ in 0..100 {
+            l.with_lock(|v| *v += 1);
+        }
+        assert_eq!(fired.load(Ordering::Relaxed), 0);
+
+        let guard = l.lock().unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| l.with_lock(|v| *v += 1));
+            while fired.load(Ordering::Relaxed) == 0 {
+                std::thread::yield_now();
+            }
+            drop(guard);
+        });
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn unsized_trait_object() {
+        let mut calls = 0;
+        {
+            let l: Box<Mutex<dyn FnMut() + Send + '_>> = Box::new(Mutex::new(|| calls += 1));
+            l.with_lock(|f| f());
+            (l.lock().unwrap())();
+        }
+        assert_eq!(calls, 2);
+
+        let slice: &Mutex<[u32]> = &Mutex::new([1, 2, 3]);
+        slice.with_lock(|s| s[0] = 10);
+        assert_eq!(slice.with_lock(|s| s.iter().sum::<u32>()), 15);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn arc_guard_moves_to_another_thread() {
+        let l = Mutex::new_arc(Vec::new());
+        let mut guard = l.lock_arc().unwrap();
+        guard.push(1);
+        let t = spawn(move || {
+            assert!(guard.mutex().is_locked());
+            guard.push(2);
+        });
+        t.join().unwrap();
+        assert!(!l.is_locked());
+        assert_eq!(l.with_lock(|v| v.clone()), [1, 2]);
+    }
+
+    #[test]
+    fn map_to_struct_field() {
+        struct Config {
+            retries: u32,
+            name: String,
+        }
+        let l = Mutex::new(Config {
+            retries: 0,
+            name: String::new(),
+        });
+        let mut retries = MutexGuard::map(l.lock().unwrap(), |c| &mut c.retries);
+        *retries += 3;
+        assert!(l.is_locked());
+        drop(retries);
+        assert!(!l.is_locked());
+        assert_eq!(l.with_lock(|c| (c.retries, c.name.is_empty())), (3, true));
+    }
+
+    #[test]
+    fn filter_map_vec_element() {
+        let l = Mutex::new(vec![1, 2, 3]);
+        let mut second = MutexGuard::filter_map(l.lock().unwrap(), |v| v.get_mut(1))
+            .ok()
+            .unwrap();
+        *second = 20;
+        drop(second);
+
+        let Err(guard) = MutexGuard::filter_map(l.lock().unwrap(), |v| v.get_mut(10)) else {
+            panic!("index 10 is out of bounds");
+        };
+        assert_eq!(*guard, [1, 20, 3]);
+        drop(guard);
+        assert!(!l.is_locked());
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn unlocked_lets_others_in() {
+        let l = Mutex::new(0);
+        let mut guard = l.lock().unwrap();
+        *guard += 1;
+        MutexGuard::unlocked(&mut guard, || {
+            assert!(!l.is_locked());
+            std::thread::scope(|s| {
+                s.spawn(|| l.with_lock(|v| *v += 10));
+            });
+        });
+        assert!(l.is_locked());
+        assert_eq!(*guard, 11);
+        drop(guard);
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut guard = l.lock().unwrap();
+            MutexGuard::unlocked(&mut guard, || panic!("boom"));
+        }));
+        assert!(r.is_err());
+        assert!(!l.is_locked());
+        assert!(l.is_poisoned());
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn try_lock_for_times_out() {
+        let l = Mutex::new(0);
+        let guard = l.lock().unwrap();
+        let start = std::time::Instant::now();
+        assert!(l.try_lock_for(Duration::from_millis(50)).is_none());
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+        drop(guard);
+
+        assert!(l.try_lock_for(Duration::from_millis(50)).is_some());
+        std::thread::scope(|s| {
+            let guard = l.lock().unwrap();
+            let waiter = s.spawn(|| l.try_lock_for(Duration::from_secs(10)).map(|mut v| *v += 1));
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap().is_some());
+        });
+        assert_eq!(l.with_lock(|v| *v), 1);
+    }
+
+    #[cfg(all(feature = "stats", not(feature = "single-thread")))]
+    #[test]
+    fn try_lock_for_records_its_wait() {
+        let l = Mutex::new(0);
+        let guard = l.lock().unwrap();
+        assert!(l.try_lock_for(Duration::from_millis(5)).is_none());
+        // Giving up isn't an acquisition
+        assert_eq!(l.stats().acquisitions, 1);
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| l.try_lock_for(Duration::from_secs(10)).is_some());
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            assert!(waiter.join().unwrap());
+        });
+        let stats = l.stats();
+        assert_eq!(stats.acquisitions, 2);
+        assert!(stats.total_spins > 0);
+        assert_eq!(l.wait_histogram()[0], 1);
+    }
+
+    #[cfg(all(feature = "debug-locks", not(feature = "single-thread")))]
+    #[test]
+    fn owner_is_the_locking_thread() {
+        let l = Mutex::new(0);
+        assert_eq!(l.owner(), None);
+        std::thread::scope(|s| {
+            let (held_tx, held_rx) = std::sync::mpsc::channel();
+            let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+            let l = &l;
+            let holder = s.spawn(move || {
+                l.with_lock(|_| {
+                    held_tx.send(std::thread::current().id()).unwrap();
+                    done_rx.recv().unwrap();
+                })
+            });
+            let id = held_rx.recv().unwrap();
+            assert_eq!(l.owner(), Some(id));
+            assert_ne!(l.owner(), Some(std::thread::current().id()));
+            done_tx.send(()).unwrap();
+            holder.join().unwrap();
+        });
+        assert_eq!(l.owner(), None);
+        let guard = MutexGuard::map(l.lock().unwrap(), |v| v);
+        assert_eq!(l.owner(), Some(std::thread::current().id()));
+        drop(guard);
+        assert_eq!(l.owner(), None);
+    }
+
+    #[cfg(feature = "debug-locks")]
+    #[test]
+    fn held_at_points_at_the_call_site() {
+        let l = Mutex::new(0);
+        assert!(l.held_at().is_none());
+
+        let guard = l.lock().unwrap();
+        let at = l.held_at().unwrap();
+        assert_eq!((at.file(), at.line()), (file!(), line!() - 2));
+        drop(guard);
+        assert!(l.held_at().is_none());
+
+        let line = line!() + 1;
+        l.with_lock(|_| assert_eq!(l.held_at().unwrap().line(), line));
+
+        let mapped = MutexGuard::map(l.try_lock().unwrap(), |v| v);
+        assert_eq!(l.held_at().unwrap().line(), line!() - 1);
+        drop(mapped);
+        assert!(l.held_at().is_none());
+    }
+
+    #[cfg(all(feature = "deadlock-detection", not(feature = "single-thread")))]
+    #[test]
+    fn opposite_lock_order_is_flagged() {
+        let a = Arc::new(Mutex::new(0));
+        let b = Arc::new(Mutex::new(0));
+        let (a2, b2) = (a.clone(), b.clone());
+        spawn(move || {
+            let _a = a2.lock().unwrap();
+            let _b = b2.lock().unwrap();
+        })
+        .join()
+        .unwrap();
+
+        // Not an actual deadlock, the first thread is done, but the order is
+        // enough to flag it
+        let err = spawn(move || {
+            let _b = b.lock().unwrap();
+            let _a = a.lock().unwrap();
+        })
+        .join()
+        .unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.starts_with("lock order inversion"), "{msg}");
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn lock2_in_opposite_orders() {
+        let a = Mutex::new(0u32);
+        let b = Mutex::new(0u32);
+        for lock2 in [lock2::<u32, u32>, lock2_retry::<u32, u32>] {
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        let (mut a, mut b) = lock2(&a, &b);
+                        *a += 1;
+                        *b += 2;
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        let (mut b, mut a) = lock2(&b, &a);
+                        *a += 1;
+                        *b += 2;
+                    }
+                });
+            });
+        }
+        assert_eq!(a.with_lock(|v| *v), 2 * 2 * 2000);
+        assert_eq!(b.with_lock(|v| *v), 2 * 2 * 2 * 2000);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "single-thread")))]
+    #[tokio::test]
+    async fn async_tasks_share_a_counter() {
+        // One thread, a task spinning for the lock would never let the holder
+        // resume
+        let l = Arc::new(Mutex::new(0));
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let l = l.clone();
+                tokio::spawn(async move {
+                    for _ in 0..500 {
+                        let mut guard = l.async_lock().await;
+                        let v = *guard;
+                        tokio::task::yield_now().await;
+                        *guard = v + 1;
+                    }
+                })
+            })
+            .collect();
+        for t in tasks {
+            t.await.unwrap();
+        }
+        assert_eq!(*l.async_lock().await, 2 * 500);
+    }
+
+    #[test]
+    fn replace_and_take() {
+        let l = Mutex::new(Some(String::from("old")));
+        assert_eq!(l.replace(Some(String::from("new"))).as_deref(), Some("old"));
+        assert_eq!(l.take().as_deref(), Some("new"));
+        assert_eq!(l.with_lock(|v| v.clone()), None);
+        assert!(!l.is_locked());
+    }
+
+    #[test]
+    fn update_moves_the_value() {
+        let l = Mutex::new(21u64);
+        l.update(|n| n * 2);
+        assert_eq!(l.with_lock(|v| *v), 42);
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            l.update(|_| panic!("boom"));
+        }));
+        assert!(r.is_err());
+        assert_eq!(l.with_lock(|v| *v), 0);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn get_unchecked_after_threads_joined() {
+        let l = Mutex::new(0u32);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| l.with_lock(|v| *v += 1));
+            }
+        });
+        // Every other user has been joined, nobody can hold a guard
+        let v = unsafe { l.get_unchecked() };
+        *v += 1;
+        assert_eq!(*v, 5);
+        assert!(!l.is_locked());
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn raw_lock_serializes_external_state() {
+        let lock = RawLock::new(());
+        let total = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let _guard = lock.guard();
+                        // Non-atomic read-modify-write, only correct under the lock
+                        let v = total.load(Ordering::Relaxed);
+                        total.store(v + 1, Ordering::Relaxed);
+                    }
+                    for _ in 0..1000 {
+                        lock.critical(|| {
+                            let v = total.load(Ordering::Relaxed);
+                            total.store(v + 1, Ordering::Relaxed);
+                        });
+                    }
+                });
+            }
+        });
+        assert_eq!(total.load(Ordering::Relaxed), 8 * 2000);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn unlock_lets_another_thread_in() {
+        let l = Mutex::new(0);
+        let mut guard = l.lock().unwrap();
+        *guard += 1;
+        MutexGuard::unlock(guard);
+        std::thread::scope(|s| {
+            let other = s.spawn(|| l.try_lock().map(|mut v| *v += 1).is_some());
+            assert!(other.join().unwrap());
+        });
+        assert_eq!(l.into_inner(), 2);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn leak_keeps_the_lock_held() {
+        let l = Mutex::new(0);
+        let v = MutexGuard::leak(l.lock().unwrap());
+        *v = 7;
+        assert!(l.is_locked());
+        std::thread::scope(|s| {
+            assert!(s.spawn(|| l.try_lock().is_none()).join().unwrap());
+        });
+        unsafe { l.force_unlock() };
+        assert_eq!(*l.try_lock().unwrap(), 7);
+        assert!(!l.is_poisoned());
+    }
+
+    #[test]
+    fn guard_as_ref_and_as_mut() {
+        fn len(s: impl AsRef<str>) -> usize {
+            s.as_ref().len()
+        }
+        fn shout(mut s: impl AsMut<str>) {
+            s.as_mut().make_ascii_uppercase();
+        }
+
+        let l = Mutex::new(String::from("spin"));
+        assert_eq!(len(l.lock().unwrap()), 4);
+        shout(l.lock().unwrap());
+        assert_eq!(l.into_inner(), "SPIN");
+    }
+
+    #[cfg(all(feature = "strong-cas", not(feature = "single-thread")))]
+    #[test]
+    fn strong_cas_never_fails_spuriously() {
+        let l = Mutex::new(0);
+        for _ in 0..10_000 {
+            *l.try_lock().expect("lock is free") += 1;
+        }
+        let l = Arc::new(l);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let l = Arc::clone(&l);
+                spawn(move || {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(l.with_lock(|v| *v), 14_000);
+    }
+
+    #[test]
+    fn try_lock_spins_gives_up_on_a_held_lock() {
+        #[derive(Clone)]
+        struct Counting(Arc<AtomicUsize>);
+
+        impl Backoff for Counting {
+            fn reset(&mut self) {}
+
+            fn snooze(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let snoozes = Arc::new(AtomicUsize::new(0));
+        let l = Mutex::with_strategy(0, Counting(Arc::clone(&snoozes)));
+        let guard = l.lock().unwrap();
+        assert_eq!(l.try_lock_spins(50).err(), Some(WouldBlock));
+        // Like lock it reads a held lock instead of snoozing, that's only
+        // for lost races
+        assert_eq!(snoozes.load(Ordering::Relaxed), 0);
+        assert_eq!(l.try_lock_spins(0).err(), Some(WouldBlock));
+        drop(guard);
+        *l.try_lock_spins(1).unwrap() += 1;
+        assert_eq!(l.into_inner(), 1);
+    }
+
+    #[test]
+    fn try_lock_spins_keeps_to_the_spin_limit() {
+        use crate::{OnSpinLimit, SpinLimit};
+
+        let l: Mutex<u32, SpinLimit<10>> =
+            Mutex::with_strategy(0, SpinLimit::new(OnSpinLimit::Panic));
+        let _guard = l.lock().unwrap();
+        // Gives up within the limit
+        assert_eq!(l.try_lock_spins(10).err(), Some(WouldBlock));
+        let past = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            l.try_lock_spins(11).is_err()
+        }));
+        assert!(past.is_err());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "unlocking a mutex that isn't locked")]
+    fn double_unlock_panics() {
+        let l = Mutex::new(0);
+        MutexGuard::leak(l.lock().unwrap());
+        unsafe {
+            l.force_unlock();
+            l.force_unlock();
+        }
+    }
+
+    #[cfg(all(debug_assertions, not(feature = "single-thread")))]
+    #[test]
+    #[should_panic(expected = "didn't lock the mutex")]
+    fn force_unlock_from_another_thread_panics() {
+        let l = Arc::new(Mutex::new(0));
+        let other = Arc::clone(&l);
+        spawn(move || {
+            MutexGuard::leak(other.lock().unwrap());
+        })
+        .join()
+        .unwrap();
+        unsafe { l.force_unlock() };
+    }
+
+    #[test]
+    fn correct_release_never_trips_the_checks() {
+        let l = Mutex::new(0);
+        for _ in 0..100 {
+            MutexGuard::leak(l.lock().unwrap());
+            unsafe { l.force_unlock() };
+            MutexGuard::unlock(l.lock().unwrap());
+            *MutexGuard::map(l.lock().unwrap(), |v| v) += 1;
+        }
+        assert_eq!(l.into_inner(), 100);
+    }
+
+    #[cfg(all(feature = "tracing", not(feature = "single-thread")))]
+    #[test]
+    #[tracing_test::traced_test]
+    fn contended_lock_emits_wait_and_held_spans() {
+        let l = Arc::new(Mutex::new(0));
+        let waiting = Arc::new(AtomicBool::new(false));
+        l.on_contention({
+            let waiting = Arc::clone(&waiting);
+            move || waiting.store(true, Ordering::Relaxed)
+        });
+
+        let guard = l.lock().unwrap();
+        let waiter = spawn({
+            let l = Arc::clone(&l);
+            // Only lines under the test's span are captured
+            let test = tracing::Span::current();
+            move || {
+                let _test = test.enter();
+                l.with_lock(|v| {
+                    *v += 1;
+                    tracing::trace!("inside");
+                })
+            }
+        });
+        while !waiting.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+        }
+        drop(guard);
+        waiter.join().unwrap();
+
+        assert!(logs_contain(":held: "));
+        logs_assert(|lines| {
+            let acquired = lines
+                .iter()
+                .find(|line| line.ends_with(" acquired"))
+                .ok_or("no acquired event")?;
+            let spins = acquired
+                .split_once(":wait{spins=")
+                .and_then(|(_, rest)| rest.split_once('}'))
+                .and_then(|(spins, _)| spins.parse::<u64>().ok())
+                .ok_or(format!("no spins in {acquired}"))?;
+            if spins == 0 {
+                return Err("waited without spinning".into());
+            }
+            Ok(())
+        });
+    }
+
+    #[cfg(any(feature = "debug-locks", feature = "tracing", feature = "metrics"))]
+    #[test]
+    fn name_shows_up_in_debug() {
+        let l = Mutex::new_named(1, "config");
+        assert_eq!(l.name(), Some("config"));
+        assert_eq!(
+            format!("{l:?}"),
+            r#"Mutex { name: "config", data: 1, poisoned: false, .. }"#
+        );
+        assert_eq!(Mutex::new(1).name(), None);
+    }
+
+    #[cfg(feature = "single-thread")]
+    #[test]
+    fn single_thread_locks_and_unlocks() {
+        let l = Mutex::new(0);
+        let mut guard = l.lock().unwrap();
+        *guard += 1;
+        assert!(l.try_lock().is_none());
+        drop(guard);
+        l.with_lock(|v| *v += 1);
+        assert_eq!(l.into_inner(), 2);
+    }
+
+    // Everything that is built on the cell based flag, on the one thread it
+    // allows
+    #[cfg(feature = "single-thread")]
+    #[test]
+    fn single_thread_smoke() {
+        // Only for lock_arc, the Arc never leaves this thread
+        #[allow(clippy::arc_with_non_send_sync)]
+        let l = Arc::new(Mutex::new(0));
+        let guard = l.try_lock_strong().unwrap();
+        assert!(l.try_lock_weak().is_none());
+        drop(guard);
+        *l.lock_arc().unwrap() += 1;
+        *MutexGuard::map(l.lock().unwrap(), |v| v) += 1;
+        assert_eq!(l.with_lock(|v| *v), 2);
+
+        let (tx, rx) = crate::channel(2);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        drop(tx);
+        assert_eq!(std::iter::from_fn(|| rx.recv()).sum::<i32>(), 3);
+
+        let sharded: crate::ShardedMutex<u32, 4> = crate::ShardedMutex::new();
+        for key in 0..16u32 {
+            sharded.with_shard(&key, |v| *v += 1);
+        }
+        assert_eq!(sharded.lock_all().iter().map(|g| **g).sum::<u32>(), 16);
+    }
+
+    #[cfg(all(feature = "single-thread", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "locked twice")]
+    fn single_thread_catches_double_lock() {
+        let l = Mutex::new(0);
+        let _guard = l.lock().unwrap();
+        let _ = l.lock();
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let l = Mutex::new(vec![1u32, 2]);
+        let copy = l.clone();
+        assert!(!l.is_locked());
+        copy.with_lock(|v| v.push(3));
+        assert_eq!(l.into_inner(), [1, 2]);
+        assert_eq!(copy.into_inner(), [1, 2, 3]);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn parks_after_spins() {
+        let l = crate::MutexBuilder::new()
+            .park_after_spins(Some(10))
+            .build(0);
+        let guard = l.lock().unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| l.with_lock(|v| *v += 1));
+            while l.parked.len() == 0 {
+                std::thread::yield_now();
+            }
+            drop(guard);
+        });
+        assert_eq!(l.with_lock(|v| *v), 1);
+        assert_eq!(l.parked.len(), 0);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn spin_limit_parks() {
+        use crate::{OnSpinLimit, SpinLimit};
+
+        let l: Mutex<u32, SpinLimit<10>> =
+            Mutex::with_strategy(0, SpinLimit::new(OnSpinLimit::Park));
+        let guard = l.lock().unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| l.with_lock(|v| *v += 1));
+            while l.parked.len() == 0 {
+                std::thread::yield_now();
+            }
+            drop(guard);
+        });
+        assert_eq!(l.with_lock(|v| *v), 1);
+        assert_eq!(l.parked.len(), 0);
+    }
+
+    struct SelfRef {
+        value: u32,
+        ptr: *const u32,
+        _pin: core::marker::PhantomPinned,
+    }
+
+    #[test]
+    fn pinned_data_stays_put() {
+        let mut m = core::pin::pin!(Mutex::new(SelfRef {
+            value: 7,
+            ptr: core::ptr::null(),
+            _pin: core::marker::PhantomPinned,
+        }));
+        let data = unsafe { m.as_mut().get_pin_mut().get_unchecked_mut() };
+        data.ptr = &data.value;
+        let addr = data.ptr;
+
+        let mut guard = m.as_ref().get_ref().lock().unwrap();
+        let pinned = unsafe { MutexGuard::as_pin_mut(&mut guard) };
+        let data = unsafe { pinned.get_unchecked_mut() };
+        assert_eq!(&data.value as *const u32, addr);
+        assert_eq!(unsafe { *data.ptr }, 7);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn arc_guard_in_a_moved_struct() {
+        struct Held {
+            guard: ArcMutexGuard<Vec<u32>>,
+        }
+
+        let m = Mutex::new_arc(Vec::new());
+        let held = Held {
+            guard: m.lock_arc().unwrap(),
+        };
+        let handle = spawn(move || {
+            let mut held = held;
+            held.guard.push(1);
+            held
+        });
+        // Still locked, the struct came back with the guard inside
+        let held = handle.join().unwrap();
+        assert!(m.is_locked());
+        spawn(move || drop(held)).join().unwrap();
+        assert!(!m.is_locked());
+        assert_eq!(m.with_lock(|v| v.clone()), [1]);
+    }
+
+    #[test]
+    fn generation_counts_releases() {
+        let m = Mutex::new(0);
+        assert_eq!(m.generation(), 0);
+        for i in 1..=5 {
+            let guard = m.lock().unwrap();
+            // Taking the lock doesn't count, only ending the critical section
+            assert_eq!(m.generation(), i - 1);
+            drop(guard);
+            assert_eq!(m.generation(), i);
+        }
+        drop(MutexGuard::map(m.lock().unwrap(), |v| v));
+        assert_eq!(m.generation(), 6);
+        let _ = MutexGuard::leak(m.lock().unwrap());
+        unsafe { m.force_unlock() };
+        assert_eq!(m.generation(), 7);
+        assert!(m.try_lock().is_some());
+        assert_eq!(m.generation(), 8);
+    }
+
+    #[test]
+    fn eq_compares_values() {
+        let a = Mutex::new(vec![1u8, 2]);
+        let b = Mutex::new(vec![1u8, 2]);
+        assert!(a == b);
+        assert!(a == a);
+        b.with_lock(|v| v.push(3));
+        assert!(a != b);
+        assert!(!a.is_locked() && !b.is_locked());
+    }
+
+    #[test]
+    fn from_and_into() {
+        let m = Mutex::from(vec![1u8]);
+        assert_eq!(m.into_inner(), [1]);
+        let ms: Vec<Mutex<u32>> = (0..3).map(Into::into).collect();
+        assert_eq!(ms.iter().map(|m| m.with_lock(|v| *v)).sum::<u32>(), 3);
+    }
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn wait_until_unlocked_sees_the_release() {
+        let m = Mutex::new(0);
+        let (held_tx, held_rx) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                m.with_lock(|v| {
+                    held_tx.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(20));
+                    *v = 1;
+                });
+            });
+            held_rx.recv().unwrap();
+            let before = m.generation();
+            m.wait_until_unlocked();
+            // The holder's critical section ended while we waited
+            assert_eq!(m.generation(), before + 1);
+            assert_eq!(unsafe { *m.data_ptr() }, 1);
+        });
+    }
+
+    #[test]
+    fn display_forwards_to_the_value() {
+        let l = Mutex::new(String::from("spin"));
+        assert_eq!(format!("{l}"), "spin");
+        // Formatting flags reach the value
+        assert_eq!(format!("{l:>6}"), "  spin");
+        let guard = l.lock().unwrap();
+        assert_eq!(format!("{l}"), "<locked>");
+        drop(guard);
+        assert_eq!(format!("{l}"), "spin");
+    }
+
+    #[test]
+    fn formatting_is_not_a_lock() {
+        let l = Mutex::new(1);
+        let before = l.generation();
+        let _ = format!("{l} {l:?}");
+        assert_eq!(l.generation(), before);
+        #[cfg(feature = "stats")]
+        assert_eq!(l.stats().acquisitions, 0);
+        l.warn_if_held_longer_than(Some(Duration::ZERO));
+        let _ = format!("{l} {l:?}");
+        assert_eq!(l.slow_holds(), 0);
+        assert!(!l.is_locked());
+    }
+
+    // The lint is right that a Mutex key could change under the set, these
+    // never do
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn mutexes_in_a_hash_set() {
+        let set: std::collections::HashSet<Mutex<u32>> =
+            [1, 2, 2, 3].into_iter().map(Mutex::new).collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&Mutex::new(2)));
+        assert!(!set.contains(&Mutex::new(4)));
+    }
+
+    #[test]
+    fn slow_holds_are_counted() {
+        let l = Mutex::new(0);
+        // Not timed without a threshold
+        l.with_lock(|_| std::thread::sleep(Duration::from_millis(20)));
+        assert_eq!(l.slow_holds(), 0);
+
+        l.warn_if_held_longer_than(Some(Duration::from_millis(10)));
+        l.with_lock(|_| std::thread::sleep(Duration::from_millis(50)));
+        assert_eq!(l.slow_holds(), 1);
+        l.with_lock(|v| *v += 1);
+        assert_eq!(l.slow_holds(), 1);
+        let mapped = MutexGuard::map(l.lock().unwrap(), |v| v);
+        std::thread::sleep(Duration::from_millis(50));
+        drop(mapped);
+        assert_eq!(l.slow_holds(), 2);
+
+        l.warn_if_held_longer_than(None);
+        l.with_lock(|_| std::thread::sleep(Duration::from_millis(20)));
+        assert_eq!(l.slow_holds(), 2);
+    }
+
+    #[test]
+    fn weak_and_strong_try_lock() {
+        let l = Mutex::new(0);
+        // Single threaded and free, the strong CAS can't fail
+        let guard = l.try_lock_strong().unwrap();
+        assert!(l.try_lock_strong().is_none());
+        assert!(l.try_lock_weak().is_none());
+        drop(guard);
+
+        // The weak one may fail spuriously, but not forever
+        let mut guard = (0..1000).find_map(|_| l.try_lock_weak()).unwrap();
+        *guard += 1;
+        assert!(l.try_lock_strong().is_none());
+        drop(guard);
+        assert_eq!(l.with_lock(|v| *v), 1);
+    }
+}
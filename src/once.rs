@@ -0,0 +1,115 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+// The initializer panicked, nobody will ever finish it
+const POISONED: u8 = 3;
+
+/// Runs a closure exactly once, no matter how many threads race on it.
+pub struct Once {
+    state: AtomicU8,
+}
+
+impl Once {
+    /// Creates a new `Once` that hasn't run yet.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+        }
+    }
+
+    /// Runs `f` if no thread has yet, otherwise waits until the thread that
+    /// is running it finishes.
+    ///
+    /// # Panics
+    ///
+    /// If the closure panicked on an earlier call, in this or another thread.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        // Fast path, Acquire so everything `f` wrote is visible to us
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            return;
+        }
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                // If `f` unwinds the drop marks the Once poisoned so the waiters don't spin forever
+                let running = Running { once: self };
+                f();
+                core::mem::forget(running);
+                self.state.store(COMPLETE, Ordering::Release);
+            }
+            Err(mut state) => loop {
+                match state {
+                    COMPLETE => return,
+                    POISONED => panic!("Once instance has previously been poisoned"),
+                    _ => {
+                        // Initializers can take a while, don't hog the core meanwhile
+                        crate::sync::yield_now();
+                        state = self.state.load(Ordering::Acquire);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Whether the closure has run to completion.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Running<'a> {
+    once: &'a Once,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.once.state.store(POISONED, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::AtomicUsize;
+    use std::thread::scope;
+
+    #[test]
+    fn runs_exactly_once() {
+        let once = Once::new();
+        let calls = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..50 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    assert!(once.is_completed());
+                });
+            }
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn panicking_initializer_poisons() {
+        let once = Once::new();
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            once.call_once(|| panic!("init failed"))
+        }));
+        assert!(r.is_err());
+        assert!(!once.is_completed());
+        let r = catch_unwind(AssertUnwindSafe(|| once.call_once(|| {})));
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use core::error::Error;
+use core::fmt;
+
+/// Returned by a lock whose previous holder panicked while holding it. The
+/// lock has still been acquired, [`PoisonError::into_inner`] hands the guard
+/// back if the data is known to be fine.
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+/// Result of acquiring a lock that may be poisoned.
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+impl<G> PoisonError<G> {
+    pub(crate) fn new(guard: G) -> Self {
+        Self { guard }
+    }
+
+    /// Consumes the error and returns the guard anyway.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    /// Reference to the guard that was acquired.
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    /// Mutable reference to the guard that was acquired.
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("poisoned lock: another task failed inside")
+    }
+}
+
+impl<G> Error for PoisonError<G> {}
+
+/// Returned by [`Mutex::try_lock_spins`](crate::Mutex::try_lock_spins) when
+/// the lock stayed held for every attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("lock still held after the allowed attempts")
+    }
+}
+
+impl Error for WouldBlock {}
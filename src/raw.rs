@@ -0,0 +1,87 @@
+use crate::Mutex;
+use core::mem;
+
+/// The bare lock behind [`Mutex`], for use with [`lock_api::Mutex`].
+///
+/// `lock` and `unlock` are the same CAS and release store `Mutex` uses, so
+/// waiters spin and back off the same way. There is no poisoning, `lock_api`
+/// guards don't report panics.
+///
+/// ```
+/// use mutex::RawSpinMutex;
+///
+/// let m = lock_api::Mutex::<RawSpinMutex, _>::new(0);
+/// *m.lock() += 1;
+/// assert_eq!(*m.lock(), 1);
+/// ```
+pub struct RawSpinMutex {
+    inner: Mutex<()>,
+}
+
+unsafe impl lock_api::RawMutex for RawSpinMutex {
+    const INIT: Self = Self {
+        inner: Mutex::new(()),
+    };
+
+    // Same as MutexGuard, the lock has no owner but we don't promise more
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock(&self) {
+        // The guard would unlock on drop, lock_api calls unlock itself
+        let guard = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        mem::forget(guard);
+    }
+
+    // Strong, lock_api callers take false to mean the lock was held
+    fn try_lock(&self) -> bool {
+        self.inner.try_lock_strong().map(mem::forget).is_some()
+    }
+
+    unsafe fn unlock(&self) {
+        self.inner.force_unlock();
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "single-thread"))]
+    use std::thread::scope;
+
+    type SpinMutex<T> = lock_api::Mutex<RawSpinMutex, T>;
+
+    #[cfg(not(feature = "single-thread"))]
+    #[test]
+    fn backs_lock_api_mutex() {
+        let l = SpinMutex::new(0);
+        scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *l.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*l.lock(), 8 * 1000);
+
+        let guard = l.lock();
+        assert!(l.is_locked());
+        assert!(l.try_lock().is_none());
+        drop(guard);
+        assert!(l.try_lock().is_some());
+    }
+
+    #[test]
+    fn mapped_guard() {
+        let l = SpinMutex::new((1, String::from("a")));
+        let mut s = lock_api::MutexGuard::map(l.lock(), |v| &mut v.1);
+        s.push('b');
+        drop(s);
+        assert_eq!(l.lock().1, "ab");
+    }
+}
@@ -0,0 +1,149 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// ThreadId can't be stored in an atomic on stable, so every thread gets its own
+// non-zero number the first time it touches a ReentrantMutex. 0 means no owner
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+fn current_thread() -> usize {
+    THREAD.with(|id| *id)
+}
+
+/// A spin lock the owning thread can take again without deadlocking. It is
+/// only released once every guard is dropped.
+///
+/// Guards only hand out `&T`, two of them can be alive on the same thread.
+pub struct ReentrantMutex<T> {
+    owner: AtomicUsize,
+    // Only touched by the owner
+    count: AtomicUsize,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for ReentrantMutex<T> where T: Send {}
+
+impl<T> ReentrantMutex<T> {
+    /// Creates a new unlocked mutex holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Spins until the lock is acquired, returns immediately if this thread
+    /// already holds it.
+    pub fn lock(&self) -> ReentrantMutexGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.owner.load(Ordering::Relaxed) != 0 {
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes the lock if it is free or already held by this thread.
+    #[must_use]
+    pub fn try_lock(&self) -> Option<ReentrantMutexGuard<'_, T>> {
+        let me = current_thread();
+        // Relaxed is fine, only this thread ever stores its own id so if we read it
+        // back we are the owner and already synchronized
+        if self.owner.load(Ordering::Relaxed) == me {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        } else if self
+            .owner
+            .compare_exchange(0, me, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.count.store(1, Ordering::Relaxed);
+        } else {
+            return None;
+        }
+        Some(ReentrantMutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Acquires the lock, runs `f` on the protected value and releases it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.lock())
+    }
+}
+
+/// RAII guard returned by [`ReentrantMutex::lock`].
+#[must_use = "if unused the lock is released immediately"]
+pub struct ReentrantMutexGuard<'a, T> {
+    mutex: &'a ReentrantMutex<T>,
+    // Must be dropped on the owning thread, the count belongs to it
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for ReentrantMutexGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for ReentrantMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T> Drop for ReentrantMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.mutex.count.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.mutex.owner.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::thread::scope;
+
+    #[test]
+    fn same_thread_can_lock_twice() {
+        let l = ReentrantMutex::new(Cell::new(0));
+        let outer = l.lock();
+        let inner = l.lock();
+        inner.set(inner.get() + 1);
+        outer.set(outer.get() + 1);
+
+        scope(|s| {
+            s.spawn(|| assert!(l.try_lock().is_none()));
+        });
+        drop(inner);
+        // Still held by the outer guard
+        scope(|s| {
+            s.spawn(|| assert!(l.try_lock().is_none()));
+        });
+        drop(outer);
+        scope(|s| {
+            s.spawn(|| assert_eq!(l.lock().get(), 2));
+        });
+    }
+
+    #[test]
+    fn recursive_with_lock() {
+        fn depth(l: &ReentrantMutex<Cell<u32>>, n: u32) {
+            if n > 0 {
+                l.with_lock(|c| c.set(c.get() + 1));
+                l.with_lock(|_| depth(l, n - 1));
+            }
+        }
+        let l = ReentrantMutex::new(Cell::new(0));
+        depth(&l, 10);
+        assert_eq!(l.lock().get(), 10);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::scope;
+
+/// Runs the store/load experiment from `examples/reordering.rs` `iterations`
+/// times and returns how often each value of `z` came out.
+///
+/// Two threads store `x` and `y` with Release. Two more wait for one of the
+/// flags with Acquire and then bump `z` if the other flag is set too. Acquire
+/// and Release only order each thread against the one it synchronised with,
+/// so the readers can disagree on which store came first and `z` can be 0,
+/// 1 or 2. Getting a 0 usually takes a weakly ordered CPU like ARM.
+///
+/// ```
+/// let hist = mutex::run_reorder_experiment(10);
+/// assert_eq!(hist.values().sum::<usize>(), 10);
+/// ```
+pub fn run_reorder_experiment(iterations: usize) -> HashMap<usize, usize> {
+    let mut hist = HashMap::new();
+    for _ in 0..iterations {
+        *hist.entry(run_once()).or_insert(0) += 1;
+    }
+    hist
+}
+
+fn run_once() -> usize {
+    let x = AtomicBool::new(false);
+    let y = AtomicBool::new(false);
+    let z = AtomicUsize::new(0);
+    scope(|s| {
+        s.spawn(|| x.store(true, Ordering::Release));
+        s.spawn(|| y.store(true, Ordering::Release));
+        s.spawn(|| {
+            while !x.load(Ordering::Acquire) {
+                std::hint::spin_loop();
+            }
+            if y.load(Ordering::Acquire) {
+                z.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        s.spawn(|| {
+            while !y.load(Ordering::Acquire) {
+                std::hint::spin_loop();
+            }
+            if x.load(Ordering::Acquire) {
+                z.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    });
+    z.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_covers_every_run() {
+        let hist = run_reorder_experiment(200);
+        assert!(hist.keys().all(|z| *z <= 2), "{hist:?}");
+        assert_eq!(hist.values().sum::<usize>(), 200);
+    }
+}
@@ -0,0 +1,496 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Lowest bit marks a writer, the next one an upgradable reader and the rest of
+// the word counts plain readers
+const WRITER: usize = 1;
+const UPGRADABLE: usize = 2;
+const READER: usize = 4;
+
+/// A spin based reader-writer lock, any number of readers or a single writer.
+///
+/// Readers are preferred: a writer waits until no readers are left. See
+/// [`RwLock::new_writer_preferred`] for the opposite.
+pub struct RwLock<T> {
+    state: AtomicUsize,
+    // Writers spinning in `write`, new readers hold back while there are any
+    // if `prefer_writers` is set
+    pending_writers: AtomicUsize,
+    prefer_writers: bool,
+    v: UnsafeCell<T>,
+}
+
+// Readers on different threads share &T so T must also be Sync
+unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+
+impl<T> RwLock<T> {
+    /// Creates a new unlocked lock holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            pending_writers: AtomicUsize::new(0),
+            prefer_writers: false,
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Creates a new unlocked lock where a waiting writer keeps new readers
+    /// out. The readers already inside finish, then the writer goes first, so
+    /// a steady stream of readers can't starve it.
+    ///
+    /// A thread that takes a second read lock while holding one deadlocks if
+    /// a writer starts waiting in between.
+    pub const fn new_writer_preferred(t: T) -> Self {
+        let mut l = Self::new(t);
+        l.prefer_writers = true;
+        l
+    }
+
+    // Whether new readers have to step aside for a waiting writer
+    fn writer_waiting(&self) -> bool {
+        self.prefer_writers && self.pending_writers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Spins until no writer holds the lock and registers as a reader.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            while self.state.load(Ordering::Relaxed) & WRITER != 0 || self.writer_waiting() {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Registers as a reader unless a writer holds the lock, or is waiting
+    /// for it with [`RwLock::new_writer_preferred`].
+    #[must_use]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        if self.writer_waiting() {
+            return None;
+        }
+        let mut s = self.state.load(Ordering::Relaxed);
+        // Other readers come and go while we try, only a writer makes us give up
+        while s & WRITER == 0 {
+            match self.state.compare_exchange_weak(
+                s,
+                s + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(RwLockReadGuard {
+                        lock: self,
+                        _not_send: PhantomData,
+                    })
+                }
+                Err(actual) => s = actual,
+            }
+        }
+        None
+    }
+
+    /// Spins until no writer or other upgradable reader holds the lock and
+    /// takes a read lock that can later be upgraded to a write lock. Plain
+    /// readers can still come and go meanwhile.
+    pub fn upgradable_read(&self) -> RwLockUpgradableGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_upgradable_read() {
+                return guard;
+            }
+            while self.state.load(Ordering::Relaxed) & (WRITER | UPGRADABLE) != 0
+                || self.writer_waiting()
+            {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes an upgradable read lock unless a writer or another upgradable
+    /// reader holds the lock, or a writer is waiting with
+    /// [`RwLock::new_writer_preferred`].
+    #[must_use]
+    pub fn try_upgradable_read(&self) -> Option<RwLockUpgradableGuard<'_, T>> {
+        if self.writer_waiting() {
+            return None;
+        }
+        let mut s = self.state.load(Ordering::Relaxed);
+        // Only one upgradable reader at a time, two of them would each wait for
+        // the other to leave before upgrading
+        while s & (WRITER | UPGRADABLE) == 0 {
+            match self.state.compare_exchange_weak(
+                s,
+                s | UPGRADABLE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(RwLockUpgradableGuard {
+                        lock: self,
+                        _not_send: PhantomData,
+                    })
+                }
+                Err(actual) => s = actual,
+            }
+        }
+        None
+    }
+
+    /// Spins until there are no readers or writers and takes the lock
+    /// exclusively.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        if let Some(guard) = self.try_write() {
+            return guard;
+        }
+        // Only a hint for readers, a reader that checked just before still gets
+        // in and we wait for it like for any other
+        self.pending_writers.fetch_add(1, Ordering::Relaxed);
+        let guard = loop {
+            if let Some(guard) = self.try_write() {
+                break guard;
+            }
+            while self.state.load(Ordering::Relaxed) != 0 {
+                core::hint::spin_loop();
+            }
+        };
+        self.pending_writers.fetch_sub(1, Ordering::Relaxed);
+        guard
+    }
+
+    /// Number of plain read guards alive right now, not counting an
+    /// upgradable reader.
+    ///
+    /// A racy snapshot, readers can come or go right after the load. Good for
+    /// monitoring, not for deciding anything.
+    pub fn reader_count(&self) -> usize {
+        self.state.load(Ordering::Relaxed) / READER
+    }
+
+    /// Takes the lock exclusively if nobody holds it.
+    #[must_use]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+}
+
+/// Shared access returned by [`RwLock::read`].
+#[must_use = "if unused the lock is released immediately"]
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for RwLockReadGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.v.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release so the next writer sees everything we read happened before it writes
+        self.lock.state.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+/// Shared access returned by [`RwLock::upgradable_read`] that can be turned
+/// into exclusive access without letting a writer in between.
+#[must_use = "if unused the lock is released immediately"]
+pub struct RwLockUpgradableGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for RwLockUpgradableGuard<'_, T> where T: Sync {}
+
+impl<'a, T> RwLockUpgradableGuard<'a, T> {
+    /// Spins until the plain readers are gone and turns this into a write
+    /// lock. No writer can get in between since we never let go.
+    pub fn upgrade(self) -> RwLockWriteGuard<'a, T> {
+        let mut this = self;
+        loop {
+            match this.try_upgrade() {
+                Ok(guard) => return guard,
+                Err(guard) => this = guard,
+            }
+            while this.lock.state.load(Ordering::Relaxed) != UPGRADABLE {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Upgrades if there are no plain readers left, hands the guard back
+    /// otherwise.
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        // Acquire pairs with the readers' Release so their reads happen before we write
+        match self.lock.state.compare_exchange(
+            UPGRADABLE,
+            WRITER,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => {
+                let lock = self.lock;
+                // The UPGRADABLE bit became WRITER, dropping would clear it again
+                core::mem::forget(self);
+                Ok(RwLockWriteGuard {
+                    lock,
+                    _not_send: PhantomData,
+                })
+            }
+            Err(_) => Err(self),
+        }
+    }
+}
+
+impl<T> Deref for RwLockUpgradableGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.v.get() }
+    }
+}
+
+impl<T> Drop for RwLockUpgradableGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(UPGRADABLE, Ordering::Release);
+    }
+}
+
+/// Exclusive access returned by [`RwLock::write`].
+#[must_use = "if unused the lock is released immediately"]
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for RwLockWriteGuard<'_, T> where T: Sync {}
+
+impl<'a, T> RwLockWriteGuard<'a, T> {
+    /// Turns the write lock into a read lock without letting go, so no other
+    /// writer can get in between. Waiting readers come in right away.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = self.lock;
+        // Nobody else can touch the state while we write, it is exactly WRITER.
+        // Release so the readers let in now see what we wrote
+        lock.state.fetch_add(READER - WRITER, Ordering::Release);
+        // The WRITER bit is gone, dropping would clear it again
+        core::mem::forget(self);
+        RwLockReadGuard {
+            lock,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.v.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.v.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(WRITER, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread::scope;
+
+    #[test]
+    fn readers_share_writer_excludes() {
+        const READERS: usize = 8;
+        let l = RwLock::new(0usize);
+        let active_readers = AtomicUsize::new(0);
+        let all_reading = Barrier::new(READERS);
+
+        scope(|s| {
+            for _ in 0..READERS {
+                s.spawn(|| {
+                    for i in 0..100 {
+                        let guard = l.read();
+                        active_readers.fetch_add(1, Ordering::SeqCst);
+                        // The first round every reader holds its guard at the same time
+                        if i == 0 {
+                            all_reading.wait();
+                        }
+                        let _ = *guard;
+                        active_readers.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..100 {
+                    let mut guard = l.write();
+                    assert_eq!(active_readers.load(Ordering::SeqCst), 0);
+                    *guard += 1;
+                }
+            });
+        });
+        assert_eq!(*l.read(), 100);
+    }
+
+    #[test]
+    fn try_variants() {
+        let l = RwLock::new(());
+        let r1 = l.read();
+        let r2 = l.try_read().unwrap();
+        assert!(l.try_write().is_none());
+        drop((r1, r2));
+        let w = l.try_write().unwrap();
+        assert!(l.try_read().is_none());
+        assert!(l.try_write().is_none());
+        drop(w);
+        assert_eq!(l.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn upgradable_allows_readers_blocks_writers() {
+        let l = RwLock::new(0);
+        let up = l.upgradable_read();
+        let r = l.try_read().unwrap();
+        assert!(l.try_write().is_none());
+        assert!(l.try_upgradable_read().is_none());
+        // A reader is still around
+        let Err(up) = up.try_upgrade() else {
+            panic!("upgraded while a reader was active");
+        };
+        drop(r);
+        let mut w = up.upgrade();
+        *w += 1;
+        assert!(l.try_read().is_none());
+        drop(w);
+        assert_eq!(l.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn waiting_writer_holds_new_readers_back() {
+        let l = RwLock::new_writer_preferred(0);
+        let r = l.read();
+        scope(|s| {
+            let writer = s.spawn(|| *l.write() += 1);
+            while l.pending_writers.load(Ordering::Relaxed) == 0 {
+                std::thread::yield_now();
+            }
+            assert!(l.try_read().is_none());
+            assert!(l.try_upgradable_read().is_none());
+            drop(r);
+            writer.join().unwrap();
+        });
+        assert_eq!(*l.try_read().unwrap(), 1);
+
+        // The default lets readers in regardless
+        let l = RwLock::new(0);
+        let _r = l.read();
+        l.pending_writers.store(1, Ordering::Relaxed);
+        assert!(l.try_read().is_some());
+    }
+
+    #[test]
+    fn writer_gets_in_under_constant_reads() {
+        let l = RwLock::new_writer_preferred(0);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let _r = l.read();
+                        for _ in 0..100 {
+                            core::hint::spin_loop();
+                        }
+                    }
+                });
+            }
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                *l.write() += 1;
+            }
+            assert!(start.elapsed() < std::time::Duration::from_secs(1));
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(*l.read(), 10);
+    }
+
+    #[test]
+    fn downgrade_lets_readers_in_but_not_writers() {
+        let l = RwLock::new(0);
+        let mut w = l.write();
+        *w += 1;
+        let r = w.downgrade();
+        assert_eq!(*r, 1);
+        scope(|s| {
+            assert_eq!(
+                s.spawn(|| l.try_read().map(|r| *r)).join().unwrap(),
+                Some(1)
+            );
+            assert!(s.spawn(|| l.try_write().is_none()).join().unwrap());
+        });
+        drop(r);
+        assert_eq!(l.state.load(Ordering::Relaxed), 0);
+        assert!(l.try_write().is_some());
+    }
+
+    #[test]
+    fn reader_count_follows_guards() {
+        let l = RwLock::new(());
+        assert_eq!(l.reader_count(), 0);
+        let readers: Vec<_> = (0..3).map(|_| l.read()).collect();
+        let up = l.upgradable_read();
+        assert_eq!(l.reader_count(), 3);
+        drop(readers);
+        assert_eq!(l.reader_count(), 0);
+        let r = up.upgrade().downgrade();
+        assert_eq!(l.reader_count(), 1);
+        drop(r);
+        let _w = l.write();
+        assert_eq!(l.reader_count(), 0);
+    }
+
+    #[test]
+    fn one_upgrade_at_a_time() {
+        let l = RwLock::new(0);
+        let writing = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let up = l.upgradable_read();
+                        let seen = *up;
+                        let mut w = up.upgrade();
+                        assert_eq!(writing.fetch_add(1, Ordering::SeqCst), 0);
+                        // Nobody wrote between our read and the upgrade
+                        assert_eq!(*w, seen);
+                        *w += 1;
+                        writing.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(*l.read(), 200);
+    }
+}
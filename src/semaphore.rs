@@ -0,0 +1,111 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counting semaphore, at most `permits` holders at a time.
+pub struct Semaphore {
+    permits: AtomicUsize,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `permits` available.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Spins until a permit is available and takes it.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            while self.permits.load(Ordering::Relaxed) == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes a permit if one is available.
+    #[must_use]
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut n = self.permits.load(Ordering::Relaxed);
+        // A plain fetch_sub could go below zero, only decrement a positive count
+        while n > 0 {
+            match self
+                .permits
+                .compare_exchange_weak(n, n - 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    return Some(SemaphorePermit {
+                        semaphore: self,
+                        _not_send: PhantomData,
+                    })
+                }
+                Err(actual) => n = actual,
+            }
+        }
+        None
+    }
+
+    /// Number of permits currently available. Racy, only useful for
+    /// diagnostics.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// A permit taken from a [`Semaphore`], given back when dropped.
+#[must_use = "if unused the permit is released immediately"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::thread::{scope, yield_now};
+
+    #[test]
+    fn never_more_than_permits_inside() {
+        let sem = Semaphore::new(3);
+        let inside = AtomicUsize::new(0);
+        let high_water = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..10 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let _permit = sem.acquire();
+                        let now = inside.fetch_add(1, Ordering::SeqCst) + 1;
+                        high_water.fetch_max(now, Ordering::SeqCst);
+                        yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert!(high_water.load(Ordering::SeqCst) <= 3);
+        assert_eq!(sem.available_permits(), 3);
+    }
+
+    #[test]
+    fn try_acquire_runs_out() {
+        let sem = Semaphore::new(2);
+        let a = sem.try_acquire().unwrap();
+        let _b = sem.try_acquire().unwrap();
+        assert!(sem.try_acquire().is_none());
+        drop(a);
+        assert!(sem.try_acquire().is_some());
+    }
+}
@@ -0,0 +1,120 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// Sequence lock for small `Copy` values that are read far more often than
+/// written. Readers never block the writer, they copy the value and retry if
+/// a write happened in the meantime.
+///
+/// An odd sequence number means a write is in progress. Writers take turns on
+/// the counter, so at most one writes at a time.
+pub struct SeqLock<T> {
+    seq: AtomicUsize,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// Creates a new sequence lock holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Returns a copy of the value, spinning while a write is in progress.
+    pub fn read(&self) -> T {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                core::hint::spin_loop();
+                continue;
+            }
+            // This copy can race with a writer and see a torn value. It's only
+            // returned if the sequence shows no write started in between.
+            // Volatile so the compiler can't assume the value is stable
+            let v = unsafe { ptr::read_volatile(self.v.get()) };
+            // Keeps the copy above from moving below the second load
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return v;
+            }
+        }
+    }
+
+    /// Replaces the value. Readers that overlap with the write retry.
+    pub fn write(&self, t: T) {
+        self.update(|_| t);
+    }
+
+    // Read-modify-write as one write, `f` must not panic or the sequence stays
+    // odd and readers spin forever
+    pub(crate) fn update(&self, f: impl FnOnce(T) -> T) {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 1 {
+                core::hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            // Going odd both tells readers to retry and locks out other writers
+            match self.seq.compare_exchange_weak(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => seq = actual,
+            }
+        }
+        // A reader that sees any of the new bytes must also see the odd count
+        fence(Ordering::Release);
+        // We are the only writer, so the current value can't be torn
+        unsafe { ptr::write_volatile(self.v.get(), f(ptr::read_volatile(self.v.get()))) };
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::thread::{scope, yield_now};
+
+    #[test]
+    fn readers_never_see_torn_values() {
+        let l = SeqLock::new([0u64; 8]);
+        let done = AtomicBool::new(false);
+        scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let v = l.read();
+                        assert!(v.iter().all(|&x| x == v[0]), "torn read {v:?}");
+                        assert!(v[0] >= last, "went back from {last} to {}", v[0]);
+                        last = v[0];
+                        yield_now();
+                    }
+                });
+            }
+            for i in 1..=10_000 {
+                l.write([i; 8]);
+                if i % 100 == 0 {
+                    yield_now();
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(l.read(), [10_000; 8]);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{Backoff, Mutex};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes the protected value. This takes the lock for as long as the
+/// serializer runs, so it spins if another thread is holding it.
+impl<T: ?Sized + Serialize, B: Backoff> Serialize for Mutex<T, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.with_lock(|v| v.serialize(serializer))
+    }
+}
+
+/// Deserializes a `T` and wraps it in an unlocked [`Mutex::new`].
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Mutex<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Mutex::new)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let l = Mutex::new(vec![1u32, 2, 3]);
+        let json = serde_json::to_string(&l).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        assert!(!l.is_locked());
+
+        let back: Mutex<Vec<u32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.into_inner(), vec![1, 2, 3]);
+    }
+}
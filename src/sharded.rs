@@ -0,0 +1,177 @@
+use crate::{CachePadded, Mutex, MutexGuard, PoisonError};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// `N` independent mutexes, each key only ever locks the shard it hashes to.
+/// Threads working on different keys mostly don't contend.
+pub struct ShardedMutex<T, const N: usize> {
+    shards: [CachePadded<Mutex<T>>; N],
+}
+
+impl<T, const N: usize> ShardedMutex<T, N> {
+    /// Creates the shards from `f(index)`.
+    pub fn from_fn(mut f: impl FnMut(usize) -> T) -> Self {
+        assert!(N > 0, "ShardedMutex needs at least one shard");
+        let shards: [CachePadded<Mutex<T>>; N] =
+            std::array::from_fn(|i| CachePadded::new(Mutex::new(f(i))));
+        // lock_all goes in index order, the lock order check has to agree
+        #[cfg(feature = "deadlock-detection")]
+        for shard in &shards {
+            shard.assign_lock_id();
+        }
+        Self { shards }
+    }
+
+    /// Creates `N` shards holding `T::default()`.
+    pub fn new() -> Self
+    where
+        T: Default,
+    {
+        Self::from_fn(|_| T::default())
+    }
+
+    /// Index of the shard `key` maps to.
+    pub fn shard_index(&self, key: &impl Hash) -> usize {
+        // DefaultHasher::new is deterministic, a key maps to the same shard every time
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % N as u64) as usize
+    }
+
+    /// Locks the shard `key` maps to and runs `f` on it.
+    ///
+    /// Holds a single shard and waits for nothing else while holding it, so
+    /// any number of `with_shard` calls can run next to [`lock_all`]. Only
+    /// nesting breaks that: calling `lock_all` from `f` deadlocks on the shard
+    /// already held, and a nested `with_shard` must go to a higher index than
+    /// the outer one to stay in `lock_all`'s order.
+    ///
+    /// [`lock_all`]: ShardedMutex::lock_all
+    pub fn with_shard<R>(&self, key: &impl Hash, f: impl FnOnce(&mut T) -> R) -> R {
+        self.shards[self.shard_index(key)].with_lock(f)
+    }
+
+    /// Locks every shard, always in ascending index order. Two `lock_all`
+    /// calls then can't deadlock each other: whoever gets shard 0 first gets
+    /// all of them, the other waits on shard 0 without holding anything.
+    pub fn lock_all(&self) -> [MutexGuard<'_, T>; N] {
+        // from_fn initializes in increasing index order, that is the guarantee
+        std::array::from_fn(|i| {
+            self.shards[i]
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+}
+
+// Rayon's workers need a Sync mutex, single-thread takes that away
+#[cfg(all(feature = "rayon", not(feature = "single-thread")))]
+impl<T: Send, const N: usize> ShardedMutex<T, N> {
+    /// Runs `f` on every item of a rayon parallel iterator, each worker
+    /// folding into the shard of its own pool index. Merge the shards at the
+    /// end, e.g. through [`lock_all`](ShardedMutex::lock_all).
+    ///
+    /// With at least as many shards as pool threads every worker has a shard
+    /// to itself, its locks are never contended and cost one uncontended CAS
+    /// per item. With fewer, workers `N` apart share a shard and contend on
+    /// it. Calls from outside a rayon pool all go to shard 0.
+    ///
+    /// ```
+    /// use mutex::ShardedMutex;
+    ///
+    /// let sums: ShardedMutex<u64, 16> = ShardedMutex::new();
+    /// sums.par_accumulate(1..=1000u64, |sum, i| *sum += i);
+    /// let total: u64 = sums.lock_all().iter().map(|shard| **shard).sum();
+    /// assert_eq!(total, 500_500);
+    /// ```
+    pub fn par_accumulate<I>(&self, iter: I, f: impl Fn(&mut T, I::Item) + Sync)
+    where
+        I: rayon::iter::IntoParallelIterator,
+    {
+        use rayon::iter::ParallelIterator;
+        iter.into_par_iter().for_each(|item| {
+            let shard = rayon::current_thread_index().unwrap_or(0) % N;
+            self.shards[shard].with_lock(|acc| f(acc, item));
+        });
+    }
+}
+
+impl<T: Default, const N: usize> Default for ShardedMutex<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Every test shares the shards between threads
+#[cfg(all(test, not(feature = "single-thread")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread::scope;
+
+    #[test]
+    fn disjoint_keys_lock_concurrently() {
+        let sharded: ShardedMutex<u32, 8> = ShardedMutex::new();
+        let a = 0u32;
+        let b = (1..)
+            .find(|k| sharded.shard_index(k) != sharded.shard_index(&a))
+            .unwrap();
+
+        let (held_tx, held_rx) = channel();
+        let (done_tx, done_rx) = channel::<()>();
+        scope(|s| {
+            let sharded = &sharded;
+            s.spawn(move || {
+                sharded.with_shard(&a, |v| {
+                    *v += 1;
+                    held_tx.send(()).unwrap();
+                    done_rx.recv().unwrap();
+                });
+            });
+            held_rx.recv().unwrap();
+            // a's shard is held by the other thread, b's is free
+            assert!(sharded.shards[sharded.shard_index(&a)].is_locked());
+            sharded.with_shard(&b, |v| *v += 1);
+            done_tx.send(()).unwrap();
+        });
+
+        let all = sharded.lock_all();
+        assert_eq!(all.iter().map(|g| **g).sum::<u32>(), 2);
+    }
+
+    #[test]
+    fn lock_all_and_with_shard_stress() {
+        let sharded: ShardedMutex<u64, 8> = ShardedMutex::new();
+        scope(|s| {
+            let sharded = &sharded;
+            for _ in 0..2 {
+                s.spawn(move || {
+                    for _ in 0..200 {
+                        let mut all = sharded.lock_all();
+                        for guard in &mut all {
+                            **guard += 1;
+                        }
+                    }
+                });
+            }
+            for t in 0..4u64 {
+                s.spawn(move || {
+                    for k in 0..500 {
+                        sharded.with_shard(&(t * 1000 + k), |v| *v += 1);
+                    }
+                });
+            }
+        });
+        let all = sharded.lock_all();
+        assert_eq!(all.iter().map(|g| **g).sum::<u64>(), 2 * 200 * 8 + 4 * 500);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_accumulate_matches_sequential_sum() {
+        let sums: ShardedMutex<u64, 8> = ShardedMutex::new();
+        sums.par_accumulate(0..1_000_000u64, |sum, i| *sum += i);
+        let total: u64 = sums.lock_all().iter().map(|g| **g).sum();
+        assert_eq!(total, (0..1_000_000u64).sum::<u64>());
+    }
+}
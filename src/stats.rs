@@ -0,0 +1,66 @@
+use crate::seqlock::SeqLock;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Upper bounds (exclusive) of the first four buckets of
+/// [`Mutex::wait_histogram`](crate::Mutex::wait_histogram), the last one
+/// takes everything from 1000 failed attempts up.
+pub const WAIT_BUCKETS: [usize; 4] = [1, 10, 100, 1000];
+
+/// Contention counters of a [`Mutex`](crate::Mutex), see
+/// [`Mutex::stats`](crate::Mutex::stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MutexStats {
+    /// Number of times the lock was taken.
+    pub acquisitions: usize,
+    /// Failed CAS attempts summed over all acquisitions.
+    pub total_spins: usize,
+}
+
+// Both counters change in a single seqlock write, so a reader always gets
+// a pair from the same moment. Only the lock holder records while resets can
+// come from anywhere, the seqlock orders the two. The histogram sits outside
+// of it, each bucket is a counter of its own and nothing pairs them
+pub(crate) struct Stats {
+    counters: SeqLock<MutexStats>,
+    waits: [AtomicUsize; 5],
+}
+
+impl Stats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            counters: SeqLock::new(MutexStats {
+                acquisitions: 0,
+                total_spins: 0,
+            }),
+            waits: [const { AtomicUsize::new(0) }; 5],
+        }
+    }
+
+    pub(crate) fn record(&self, failed_cas: usize) {
+        self.counters.update(|s| MutexStats {
+            acquisitions: s.acquisitions.wrapping_add(1),
+            total_spins: s.total_spins.wrapping_add(failed_cas),
+        });
+        // Comparisons summed instead of a chain of ifs, no branches to mispredict
+        let bucket = WAIT_BUCKETS
+            .iter()
+            .map(|&b| (failed_cas >= b) as usize)
+            .sum::<usize>();
+        self.waits[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> MutexStats {
+        self.counters.read()
+    }
+
+    pub(crate) fn waits(&self) -> [usize; 5] {
+        core::array::from_fn(|i| self.waits[i].load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn reset(&self) {
+        self.counters.write(MutexStats::default());
+        for bucket in &self.waits {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
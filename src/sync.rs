@@ -0,0 +1,204 @@
+// Everything the locks need from the platform goes through here. Built with
+// `RUSTFLAGS="--cfg loom"` the atomics and threads become loom's, so the model
+// checker can explore every interleaving, see tests/loom.rs. With
+// `--cfg shuttle` they become shuttle's instead, for its randomized schedules,
+// see tests/shuttle.rs. Plain builds get core's and std's, unchanged. Not
+// every feature combination uses every item, hence the allows
+
+#[cfg(all(not(any(loom, shuttle)), not(feature = "single-thread")))]
+pub(crate) use core::sync::atomic::AtomicBool;
+#[cfg(not(any(loom, shuttle)))]
+#[allow(unused_imports)]
+pub(crate) use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(loom)]
+#[allow(unused_imports)]
+pub(crate) use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(all(shuttle, not(loom)))]
+#[allow(unused_imports)]
+pub(crate) use shuttle::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(all(not(any(loom, shuttle)), feature = "single-thread"))]
+pub(crate) use single_thread::AtomicBool;
+
+// Always core's. loom's checked cell has a different API (with / with_mut
+// instead of get) and shuttle has none, so a model test that wants its
+// accesses tracked puts a loom UnsafeCell inside the Mutex instead
+pub(crate) use core::cell::UnsafeCell;
+
+#[cfg(loom)]
+#[allow(unused_imports)]
+pub(crate) use loom::thread;
+#[cfg(all(shuttle, not(loom)))]
+#[allow(unused_imports)]
+pub(crate) use shuttle::thread;
+#[cfg(all(not(any(loom, shuttle)), feature = "std"))]
+#[allow(unused_imports)]
+pub(crate) use std::thread;
+
+// Same interface as the atomic, as plain loads and stores. Not Sync, and with
+// it neither is Mutex, so the compiler keeps it on one thread
+#[cfg(all(not(any(loom, shuttle)), feature = "single-thread"))]
+mod single_thread {
+    use super::Ordering;
+    use core::cell::Cell;
+
+    pub(crate) struct AtomicBool(Cell<bool>);
+
+    impl AtomicBool {
+        pub(crate) const fn new(v: bool) -> Self {
+            Self(Cell::new(v))
+        }
+
+        pub(crate) fn load(&self, _: Ordering) -> bool {
+            self.0.get()
+        }
+
+        pub(crate) fn store(&self, v: bool, _: Ordering) {
+            self.0.set(v);
+        }
+
+        pub(crate) fn swap(&self, v: bool, _: Ordering) -> bool {
+            self.0.replace(v)
+        }
+
+        pub(crate) fn compare_exchange(
+            &self,
+            current: bool,
+            new: bool,
+            _: Ordering,
+            _: Ordering,
+        ) -> Result<bool, bool> {
+            let v = self.0.get();
+            if v == current {
+                self.0.set(new);
+                Ok(v)
+            } else {
+                Err(v)
+            }
+        }
+
+        pub(crate) fn compare_exchange_weak(
+            &self,
+            current: bool,
+            new: bool,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<bool, bool> {
+            self.compare_exchange(current, new, success, failure)
+        }
+    }
+}
+
+// Busy waits must go through loom or shuttle or they never schedule the holder
+#[cfg(not(any(loom, shuttle)))]
+pub(crate) use core::hint::spin_loop;
+#[cfg(loom)]
+pub(crate) use loom::hint::spin_loop;
+#[cfg(all(shuttle, not(loom)))]
+pub(crate) use shuttle::hint::spin_loop;
+#[cfg(any(loom, shuttle, all(feature = "std", not(target_arch = "wasm32"))))]
+pub(crate) use thread::yield_now;
+
+// Without std there is no scheduler to hand the time slice to. wasm has std but
+// its yield_now is a no-op, and a worker can't give its time slice away either
+#[cfg(all(
+    not(any(loom, shuttle)),
+    any(not(feature = "std"), target_arch = "wasm32")
+))]
+pub(crate) fn yield_now() {
+    core::hint::spin_loop();
+}
+
+// Pulls the cache line at `p` into this core's cache in exclusive state, so
+// the CAS that follows doesn't have to upgrade it from shared first. Only a
+// hint, a no-op without the prefetch feature, under loom and on targets other
+// than x86 with SSE and aarch64
+#[inline]
+pub(crate) fn prefetch_write<T>(p: *const T) {
+    #[cfg(all(
+        feature = "prefetch",
+        not(loom),
+        any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse")
+        )
+    ))]
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        use core::arch::x86::{_mm_prefetch, _MM_HINT_ET0};
+        #[cfg(target_arch = "x86_64")]
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_ET0};
+        // Never faults, even on an invalid address
+        _mm_prefetch::<_MM_HINT_ET0>(p.cast());
+    }
+    #[cfg(all(feature = "prefetch", not(loom), target_arch = "aarch64"))]
+    unsafe {
+        // Prefetch for store, into L1
+        core::arch::asm!("prfm pstl1keep, [{}]", in(reg) p, options(nostack, preserves_flags));
+    }
+    let _ = p;
+}
+
+// Whether the current thread is unwinding, used to poison a lock whose guard is
+// dropped mid-panic. no_std targets can't ask, so their locks never poison
+#[cfg(feature = "std")]
+pub(crate) use std::thread::panicking;
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn panicking() -> bool {
+    false
+}
+
+// Tells threads apart cheaply for debug checks, the address of a thread local
+// is unique among the live threads
+#[cfg(all(debug_assertions, feature = "std"))]
+pub(crate) fn thread_token() -> usize {
+    std::thread_local!(static TOKEN: u8 = const { 0 });
+    TOKEN.with(|t| t as *const u8 as usize)
+}
+
+// The current thread's id at a 'static address, so a lock can point at its
+// holder's with a single atomic store. Leaked once per thread that asks, the
+// pointer may be read after the thread is gone
+#[cfg(all(feature = "debug-locks", feature = "std"))]
+pub(crate) fn current_thread_id() -> &'static std::thread::ThreadId {
+    std::thread_local!(static ID: &'static std::thread::ThreadId =
+        Box::leak(Box::new(std::thread::current().id())));
+    ID.with(|id| *id)
+}
+
+// loom's atomics can't be built in a const context, so constructors are only
+// const outside of loom
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis const fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])* $vis const fn $($rest)*
+        #[cfg(loom)]
+        $(#[$attr])* $vis fn $($rest)*
+    };
+}
+pub(crate) use const_fn;
+
+#[cfg(all(test, feature = "std", not(any(loom, shuttle))))]
+mod tests {
+    use core::any::TypeId;
+
+    // The default build must not pay for the swap, the re-exports are exactly
+    // the standard types
+    #[test]
+    fn default_build_uses_std() {
+        #[cfg(not(feature = "single-thread"))]
+        assert_eq!(
+            TypeId::of::<super::AtomicBool>(),
+            TypeId::of::<std::sync::atomic::AtomicBool>()
+        );
+        assert_eq!(
+            TypeId::of::<super::AtomicUsize>(),
+            TypeId::of::<std::sync::atomic::AtomicUsize>()
+        );
+        assert_eq!(
+            TypeId::of::<super::UnsafeCell<u8>>(),
+            TypeId::of::<std::cell::UnsafeCell<u8>>()
+        );
+        assert_eq!(super::thread::current().id(), std::thread::current().id());
+    }
+}
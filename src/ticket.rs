@@ -0,0 +1,124 @@
+use crate::YIELD_THRESHOLD;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fair spin lock, threads are served in the order they called
+/// [`TicketMutex::lock`].
+pub struct TicketMutex<T> {
+    // Like the queue at a deli counter: take a number, wait until it is called
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    v: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for TicketMutex<T> where T: Send {}
+
+impl<T> TicketMutex<T> {
+    /// Creates a new unlocked mutex holding `t`.
+    pub const fn new(t: T) -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            v: UnsafeCell::new(t),
+        }
+    }
+
+    /// Takes a ticket and spins until it is served.
+    pub fn lock(&self) -> TicketMutexGuard<'_, T> {
+        // Relaxed is enough, the ticket only decides our place in the line. Wrapping
+        // on overflow is fine as long as fewer than usize::MAX threads wait at once
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        // Acquire pairs with the Release of the previous holder handing over
+        let mut spins = 0;
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            // The lock is handed to one particular thread, if that thread isn't running
+            // nobody makes progress. Once we've waited a while let the scheduler run it
+            spins += 1;
+            if spins > YIELD_THRESHOLD {
+                crate::sync::yield_now();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+        TicketMutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquires the lock, runs `f` on the protected value and releases it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+}
+
+/// RAII guard returned by [`TicketMutex::lock`].
+#[must_use = "if unused the lock is released immediately"]
+pub struct TicketMutexGuard<'a, T> {
+    mutex: &'a TicketMutex<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for TicketMutexGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for TicketMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.v.get() }
+    }
+}
+
+impl<T> DerefMut for TicketMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.v.get() }
+    }
+}
+
+impl<T> Drop for TicketMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Call the next number
+        self.mutex.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::thread::{scope, yield_now};
+
+    #[test]
+    fn counter() {
+        let l = TicketMutex::new(0);
+        scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(l.with_lock(|v| *v), 16 * 1000);
+    }
+
+    #[test]
+    fn served_in_arrival_order() {
+        let l = TicketMutex::new(Vec::new());
+        let guard = l.lock();
+        scope(|s| {
+            for i in 0..8 {
+                let l = &l;
+                s.spawn(move || l.with_lock(|v| v.push(i)));
+                // Wait until thread i holds its ticket before starting the next one
+                while l.next_ticket.load(Ordering::Relaxed) != i + 2 {
+                    yield_now();
+                }
+            }
+            drop(guard);
+        });
+        assert_eq!(l.with_lock(|v| v.clone()), (0..8).collect::<Vec<_>>());
+    }
+}
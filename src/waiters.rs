@@ -0,0 +1,78 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, Thread};
+
+/// FIFO of parked threads, guarded by its own tiny spin lock. The critical
+/// sections are a handful of instructions so spinning here is fine even for
+/// the primitives that park.
+pub(crate) struct WaitQueue {
+    locked: AtomicBool,
+    threads: UnsafeCell<VecDeque<Thread>>,
+}
+
+unsafe impl Sync for WaitQueue {}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            threads: UnsafeCell::new(VecDeque::new()),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut VecDeque<Thread>) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                std::hint::spin_loop();
+            }
+        }
+        let ret = f(unsafe { &mut *self.threads.get() });
+        self.locked.store(false, Ordering::Release);
+        ret
+    }
+
+    /// Adds the current thread to the back of the queue unless it is already
+    /// waiting (a spurious wake up puts it back here while still queued).
+    pub(crate) fn register(&self) {
+        let current = thread::current();
+        self.with(|q| {
+            if !q.iter().any(|t| t.id() == current.id()) {
+                q.push_back(current);
+            }
+        });
+    }
+
+    /// Removes the current thread from the queue if it is still there.
+    pub(crate) fn deregister(&self) {
+        let id = thread::current().id();
+        self.with(|q| q.retain(|t| t.id() != id));
+    }
+
+    /// Unparks the longest waiting thread, returns false if nobody was waiting.
+    pub(crate) fn wake_one(&self) -> bool {
+        match self.with(|q| q.pop_front()) {
+            Some(t) => {
+                t.unpark();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unparks every queued thread.
+    pub(crate) fn wake_all(&self) {
+        for t in self.with(std::mem::take) {
+            t.unpark();
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.with(|q| q.len())
+    }
+}
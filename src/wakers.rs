@@ -0,0 +1,75 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::Waker;
+
+/// FIFO of wakers of tasks waiting in [`Mutex::async_lock`], the async
+/// counterpart of `WaitQueue`.
+///
+/// [`Mutex::async_lock`]: crate::Mutex::async_lock
+pub(crate) struct WakerQueue {
+    locked: AtomicBool,
+    // Mirrors the queue length so unlocking without waiters is one load
+    len: AtomicUsize,
+    wakers: UnsafeCell<VecDeque<Waker>>,
+}
+
+unsafe impl Sync for WakerQueue {}
+unsafe impl Send for WakerQueue {}
+
+impl WakerQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            len: AtomicUsize::new(0),
+            wakers: UnsafeCell::new(VecDeque::new()),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut VecDeque<Waker>) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                std::hint::spin_loop();
+            }
+        }
+        let q = unsafe { &mut *self.wakers.get() };
+        let ret = f(q);
+        self.len.store(q.len(), Ordering::Relaxed);
+        self.locked.store(false, Ordering::Release);
+        ret
+    }
+
+    /// Adds `waker` to the back of the queue unless it is already waiting, a
+    /// task can be polled again before it is woken.
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.with(|q| {
+            if !q.iter().any(|w| w.will_wake(waker)) {
+                q.push_back(waker.clone());
+            }
+        });
+    }
+
+    /// Removes `waker`, returns false if it was already woken.
+    pub(crate) fn deregister(&self, waker: &Waker) -> bool {
+        self.with(|q| {
+            let before = q.len();
+            q.retain(|w| !w.will_wake(waker));
+            q.len() != before
+        })
+    }
+
+    /// Wakes the longest waiting task, if any.
+    pub(crate) fn wake_one(&self) {
+        if self.len.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        // Wake outside the queue's lock, the waker may run arbitrary code
+        if let Some(w) = self.with(|q| q.pop_front()) {
+            w.wake();
+        }
+    }
+}
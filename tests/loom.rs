@@ -0,0 +1,51 @@
+// Model checks the lock under every interleaving loom can find
+//
+//     RUSTFLAGS="--cfg loom" cargo test --release --test loom
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use mutex::Mutex;
+
+#[test]
+fn two_threads_increment() {
+    loom::model(|| {
+        let l = Arc::new(Mutex::new(0usize));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let l = l.clone();
+                thread::spawn(move || l.with_lock(|v| *v += 1))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(l.with_lock(|v| *v), 2);
+    });
+}
+
+// With Relaxed on both sides nothing orders the first holder's write before the
+// second holder's read, loom reports the unsynchronized access to the cell
+#[test]
+#[should_panic(expected = "Causality violation")]
+fn relaxed_ordering_is_a_data_race() {
+    use loom::cell::UnsafeCell;
+    use std::sync::atomic::Ordering;
+
+    loom::model(|| {
+        let l = Arc::new(Mutex::new_with_ordering(
+            UnsafeCell::new(0usize),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let l = l.clone();
+                thread::spawn(move || l.with_lock(|c| c.with_mut(|v| unsafe { *v += 1 })))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    });
+}
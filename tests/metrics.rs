@@ -0,0 +1,113 @@
+// The metrics feature against a recorder that just tallies what it is given.
+// Its own binary, the recorder is process wide
+#![cfg(all(feature = "metrics", not(feature = "single-thread")))]
+
+use metrics::{
+    Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+use mutex::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+
+#[derive(Default)]
+struct Tally {
+    count: AtomicU64,
+    // Histogram samples
+    samples: StdMutex<Vec<f64>>,
+}
+
+impl CounterFn for Tally {
+    fn increment(&self, value: u64) {
+        self.count.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.count.store(value, Ordering::Relaxed);
+    }
+}
+
+impl HistogramFn for Tally {
+    fn record(&self, value: f64) {
+        self.samples.lock().unwrap().push(value);
+    }
+}
+
+#[derive(Default)]
+struct TestRecorder {
+    metrics: StdMutex<Vec<(Key, Arc<Tally>)>>,
+}
+
+impl TestRecorder {
+    fn tally(&self, key: &Key) -> Arc<Tally> {
+        let mut metrics = self.metrics.lock().unwrap();
+        if let Some((_, t)) = metrics.iter().find(|(k, _)| k == key) {
+            return t.clone();
+        }
+        let t = Arc::new(Tally::default());
+        metrics.push((key.clone(), t.clone()));
+        t
+    }
+
+    // The tally for `metric` labelled `name = name`
+    fn get(&self, metric: &str, name: &str) -> Option<Arc<Tally>> {
+        let metrics = self.metrics.lock().unwrap();
+        metrics
+            .iter()
+            .find(|(k, _)| {
+                k.name() == metric && k.labels().any(|l| l.key() == "name" && l.value() == name)
+            })
+            .map(|(_, t)| t.clone())
+    }
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.tally(key))
+    }
+
+    fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.tally(key))
+    }
+}
+
+#[test]
+fn named_mutexes_report_acquisitions_and_spins() {
+    let recorder: &'static TestRecorder = Box::leak(Box::default());
+    metrics::set_global_recorder(recorder).unwrap();
+
+    let named = Mutex::new_named(0u64, "jobs");
+    let unnamed = Mutex::new(0u64);
+    let guard = named.lock().unwrap();
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| named.with_lock(|v| *v += 1));
+        }
+        // Gives the waiters time to find the lock held
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        drop(guard);
+    });
+    unnamed.with_lock(|v| *v += 1);
+    assert!(named.try_lock().is_some());
+
+    let acquisitions = recorder.get("mutex.acquisitions", "jobs").unwrap();
+    // The held guard, four waiters and the try_lock
+    assert_eq!(acquisitions.count.load(Ordering::Relaxed), 6);
+    let spins = recorder.get("mutex.wait_spins", "jobs").unwrap();
+    let samples = spins.samples.lock().unwrap();
+    assert_eq!(samples.len(), 6);
+    assert!(
+        samples.iter().any(|&s| s > 0.0),
+        "nobody waited: {samples:?}"
+    );
+    // Nothing without a name
+    assert_eq!(recorder.metrics.lock().unwrap().len(), 2);
+}
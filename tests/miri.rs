@@ -0,0 +1,77 @@
+// Small single-threaded cases for Miri to check the UnsafeCell accesses
+// behind the guards against its aliasing model
+//
+//     cargo +nightly miri test --test miri
+//
+// They pass under plain `cargo test` too. Miri can't say anything about the
+// atomics' happens-before across threads, that's what tests/loom.rs is for.
+#![cfg(not(loom))]
+
+use mutex::{Mutex, MutexGuard};
+
+#[test]
+fn lock_mutate_unlock() {
+    let m = Mutex::new(vec![1]);
+    {
+        let mut guard = m.lock().unwrap();
+        guard.push(2);
+        // A shared reborrow next to the mutable one above, both through the guard
+        assert_eq!(guard.len(), 2);
+    }
+    m.with_lock(|v| v.push(3));
+    assert_eq!(m.into_inner(), [1, 2, 3]);
+}
+
+#[test]
+fn references_end_with_the_guard() {
+    let m = Mutex::new(0);
+    {
+        let mut guard = m.lock().unwrap();
+        let first: &mut i32 = &mut guard;
+        *first += 1;
+    }
+    // The previous guard is gone, a fresh &mut must not conflict with it
+    let mut second = m.lock().unwrap();
+    *second += 1;
+    drop(second);
+    assert_eq!(*m.lock().unwrap(), 2);
+}
+
+#[test]
+fn mapped_guard_and_unlocked() {
+    let m = Mutex::new((0, String::new()));
+    let mut guard = m.lock().unwrap();
+    // Releases and relocks while `guard` is still borrowed mutably
+    MutexGuard::unlocked(&mut guard, || m.with_lock(|v| v.0 = 1));
+    let mut name = MutexGuard::map(guard, |v| &mut v.1);
+    name.push_str("spin");
+    drop(name);
+    assert_eq!(m.into_inner(), (1, String::from("spin")));
+}
+
+#[test]
+fn leak_then_force_unlock() {
+    let m = Mutex::new(Box::new(0));
+    let v = MutexGuard::leak(m.lock().unwrap());
+    **v = 5;
+    unsafe { m.force_unlock() };
+    assert_eq!(**m.lock().unwrap(), 5);
+}
+
+#[test]
+fn unsized_and_raw_access() {
+    let mut m: Box<Mutex<[u8]>> = Box::new(Mutex::new([1, 2, 3]));
+    m.lock().unwrap()[0] = 9;
+    m.get_mut()[1] = 8;
+    unsafe { (*m.data_ptr())[2] = 7 };
+    assert_eq!(&*m.lock().unwrap(), &[9, 8, 7]);
+}
+
+#[test]
+fn replace_take_update() {
+    let m = Mutex::new(String::from("a"));
+    assert_eq!(m.replace(String::from("b")), "a");
+    m.update(|s| s + "c");
+    assert_eq!(m.take(), "bc");
+    assert_eq!(m.into_inner(), "");
+}
@@ -0,0 +1,81 @@
+// Every test here shares a Mutex between threads
+#![cfg(not(feature = "single-thread"))]
+
+use mutex::{spinlock, Mutex};
+use proptest::prelude::*;
+use std::thread::spawn;
+
+#[test]
+fn counter_from_many_threads() {
+    let l: &'static _ = Box::leak(Box::new(Mutex::new(0)));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            spawn(move || {
+                for _ in 0..1000 {
+                    l.with_lock(|v| *v += 1);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(l.with_lock(|v| *v), 8 * 1000);
+}
+
+#[test]
+fn scoped_threads_share_a_stack_mutex() {
+    let l = Mutex::new(0u64);
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    l.with_lock(|v| *v += 1);
+                }
+            });
+        }
+    });
+    assert_eq!(l.into_inner(), 8 * 1000);
+}
+
+// Random thread and increment counts, the total must always come out exact.
+// proptest shrinks a failure down to the smallest case it can find
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn random_counter_cases(threads in 1..=32u64, increments in 0..=2000u64) {
+        let l = Mutex::new(0u64);
+        std::thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    for _ in 0..increments {
+                        l.with_lock(|v| *v += 1);
+                    }
+                });
+            }
+        });
+        prop_assert_eq!(l.into_inner(), threads * increments);
+    }
+}
+
+spinlock!(
+    static HITS: Mutex<u64> = 0;
+);
+
+#[test]
+fn static_from_the_macro() {
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            spawn(|| {
+                for _ in 0..1000 {
+                    HITS.with_lock(|v| *v += 1);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(HITS.with_lock(|v| *v), 8 * 1000);
+}
@@ -0,0 +1,83 @@
+// Runs the lock under shuttle's randomized schedules. It doesn't prove every
+// interleaving like tests/loom.rs, but it scales to more threads and steps
+//
+//     RUSTFLAGS="--cfg shuttle" cargo test --release --test shuttle
+#![cfg(shuttle)]
+
+use mutex::Mutex;
+use shuttle::sync::Arc;
+use shuttle::thread;
+use std::collections::VecDeque;
+
+const ITERATIONS: usize = 1000;
+
+#[test]
+fn counter_from_many_threads() {
+    shuttle::check_random(
+        || {
+            let l = Arc::new(Mutex::new(0usize));
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let l = l.clone();
+                    thread::spawn(move || {
+                        for _ in 0..5 {
+                            l.with_lock(|v| *v += 1);
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(l.with_lock(|v| *v), 4 * 5);
+        },
+        ITERATIONS,
+    );
+}
+
+// Condvar parks through std, which shuttle can't schedule around, so the
+// consumers poll the queue instead. Every item must come out exactly once
+#[test]
+fn producers_and_consumers() {
+    shuttle::check_random(
+        || {
+            const PER_PRODUCER: usize = 3;
+            let queue = Arc::new(Mutex::new(VecDeque::new()));
+            let producers: Vec<_> = (0..2)
+                .map(|p| {
+                    let queue = queue.clone();
+                    thread::spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            queue.with_lock(|q| q.push_back(p * PER_PRODUCER + i));
+                        }
+                    })
+                })
+                .collect();
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    let queue = queue.clone();
+                    thread::spawn(move || {
+                        let mut got = Vec::new();
+                        while got.len() < PER_PRODUCER {
+                            match queue.with_lock(|q| q.pop_front()) {
+                                Some(v) => got.push(v),
+                                None => thread::yield_now(),
+                            }
+                        }
+                        got
+                    })
+                })
+                .collect();
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            let mut all: Vec<_> = consumers
+                .into_iter()
+                .flat_map(|c| c.join().unwrap())
+                .collect();
+            all.sort();
+            assert_eq!(all, (0..2 * PER_PRODUCER).collect::<Vec<_>>());
+        },
+        ITERATIONS,
+    );
+}
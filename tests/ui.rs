@@ -0,0 +1,12 @@
+// Code the API must reject, with the errors pinned in tests/ui/*.stderr.
+// Regenerate them after an intended change with
+//
+//     TRYBUILD=overwrite cargo test --test ui
+//
+// single-thread makes every Mutex !Sync, which changes the errors
+#![cfg(not(any(loom, shuttle, miri, feature = "single-thread")))]
+
+#[test]
+fn ui() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
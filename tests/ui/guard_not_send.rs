@@ -0,0 +1,5 @@
+fn main() {
+    let m: &'static _ = Box::leak(Box::new(mutex::Mutex::new(0)));
+    let guard = m.lock().unwrap();
+    std::thread::spawn(move || drop(guard));
+}
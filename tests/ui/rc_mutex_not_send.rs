@@ -0,0 +1,6 @@
+use std::rc::Rc;
+
+fn main() {
+    let m = mutex::Mutex::new(Rc::new(0u8));
+    std::thread::spawn(move || drop(m));
+}
@@ -0,0 +1,8 @@
+use std::rc::Rc;
+
+fn main() {
+    let m = mutex::Mutex::new(Rc::new(0u8));
+    std::thread::scope(|s| {
+        s.spawn(|| drop(m.lock()));
+    });
+}
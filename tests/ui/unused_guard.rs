@@ -0,0 +1,6 @@
+#![deny(unused_must_use)]
+
+fn main() {
+    let m = mutex::Mutex::new(0);
+    m.lock().unwrap();
+}